@@ -0,0 +1,103 @@
+use rust_software_rendering::buffer::*;
+use rust_software_rendering::shader::*;
+use rust_software_rendering::state::*;
+use rust_software_rendering::utils::*;
+
+use std::time::{Duration, Instant};
+
+// Compares the scalar and wide paths, with a shader shading all lanes at once and one going
+// through `fragment` per lane. Run with --release, and --features f32 for SIMD backed vectors.
+
+const SIZE: usize = 1024;
+const FRAMES: u32 = 20;
+
+struct Gradient;
+
+impl Shader for Gradient {
+    type Vertex = (Vec3, Vec3);
+    type VertexShaderOut = Vec3;
+
+    fn vertex(&self, vertex: &(Vec3, Vec3)) -> (Vec4, Vec3) {
+        (vertex.0.extend(1.0), vertex.1)
+    }
+
+    fn fragment(&self, color: &Vec3) -> Vec4 {
+        (*color * *color).extend(1.0)
+    }
+
+    fn fragment_wide(&self, varyings: [&Vec3; 3], weights: &[Vec4; 3], _: [bool; LANES]) -> [Vec4; LANES] {
+        let [c0, c1, c2] = varyings;
+        let r = interpolate_lanes(c0.x, c1.x, c2.x, weights);
+        let g = interpolate_lanes(c0.y, c1.y, c2.y, weights);
+        let b = interpolate_lanes(c0.z, c1.z, c2.z, weights);
+        let (r, g, b) = (r * r, g * g, b * b);
+
+        [0, 1, 2, 3].map(|lane| Vec4::new(r[lane], g[lane], b[lane], 1.0))
+    }
+}
+
+// Same colors through the default `fragment_wide`
+struct GradientPerLane;
+
+impl Shader for GradientPerLane {
+    type Vertex = (Vec3, Vec3);
+    type VertexShaderOut = Vec3;
+
+    fn vertex(&self, vertex: &(Vec3, Vec3)) -> (Vec4, Vec3) {
+        Gradient.vertex(vertex)
+    }
+
+    fn fragment(&self, color: &Vec3) -> Vec4 {
+        Gradient.fragment(color)
+    }
+}
+
+// Full screen quads stacked in depth, drawn back to front so every one passes the depth test,
+// then front to back so all but the first are rejected by it
+fn layers() -> (Vec<(Vec3, Vec3)>, Vec<usize>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    for layer in 0..8 {
+        let depth = layer as real * 0.1;
+        let tint = Vec3::new(depth, 0.5, 1.0 - depth);
+        let base = vertices.len();
+
+        vertices.push((Vec3::new(-1.0, -1.0, depth), tint));
+        vertices.push((Vec3::new(1.0, -1.0, depth), Vec3::X));
+        vertices.push((Vec3::new(-1.0, 1.0, depth), Vec3::Y));
+        vertices.push((Vec3::new(1.0, 1.0, depth), Vec3::Z));
+        indices.extend([0, 1, 2, 3, 2, 1].map(|i| base + i));
+    }
+
+    let back_to_front = indices.clone();
+    indices.extend(back_to_front.chunks(6).rev().flatten());
+
+    (vertices, indices)
+}
+
+fn time<S: Shader<Vertex = (Vec3, Vec3)>>(shader: &S, wide: bool) -> Duration {
+    let (vertices, indices) = layers();
+    let mut buffer = Buffer::new(SIZE, SIZE, 4);
+    let now = Instant::now();
+
+    for _ in 0..FRAMES {
+        buffer.clear();
+        let mut state = DrawState { wide, ..Default::default() };
+        shader.draw_with(&mut buffer, &vertices, &indices, &mut state).unwrap();
+    }
+
+    now.elapsed() / FRAMES
+}
+
+fn main() {
+    for (name, scalar, wide) in [
+        ("fragment_wide", time(&Gradient, false), time(&Gradient, true)),
+        ("fragment per lane", time(&GradientPerLane, false), time(&GradientPerLane, true)),
+    ] {
+        println!(
+            "{}: scalar {:.2?}, wide {:.2?} ({:.2}x)",
+            name, scalar, wide, scalar.as_secs_f64() / wide.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::utils::*;
+use crate::texture::*;
+
+// Layers of the same size sampled as one texture. Filtering and wrapping never read across
+// layers, so neighbouring sprites or terrain materials don't bleed into each other.
+// Always holds at least one layer.
+pub struct TextureArray {
+    layers: Vec<Texture>,
+}
+
+impl TextureArray {
+    pub fn from_layers(layers: Vec<Texture>) -> Result<TextureArray, &'static str> {
+        let (width, height) = match layers.first() {
+            Some(layer) => (layer.width, layer.height),
+            None => return Err("Texture array needs at least one layer!"),
+        };
+
+        if layers.iter().any(|layer| layer.width != width || layer.height != height) {
+            Err("Texture array layers must be of the same size!")
+        } else {
+            Ok(TextureArray { layers })
+        }
+    }
+
+    // Sprite sheet of `columns` by `rows` equal cells, layers going row by row from the top left
+    pub fn from_grid(sheet: &Texture, columns: usize, rows: usize) -> Result<TextureArray, &'static str> {
+        if columns == 0 || rows == 0 || sheet.width / columns * columns != sheet.width || sheet.height / rows * rows != sheet.height {
+            return Err("Sheet doesn't divide into the grid evenly!");
+        }
+
+        let (width, height) = (sheet.width / columns, sheet.height / rows);
+        let layers = (0..columns * rows)
+            .map(|i| sheet.region(i % columns * width, i / columns * height, width, height))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        TextureArray::from_layers(layers)
+    }
+
+    pub fn generate_mipmaps(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.generate_mipmaps();
+        }
+    }
+
+    pub fn levels(&self) -> usize {
+        self.layers[0].levels()
+    }
+
+    pub fn layers(&self) -> &[Texture] {
+        &self.layers
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    // Layers are picked by rounding and clamped to the ones that exist, never blended
+    pub fn layer(&self, layer: real) -> &Texture {
+        let index = layer.round().clamp(0.0, (self.layers.len() - 1) as real) as usize;
+        &self.layers[index]
+    }
+
+    pub fn sample(&self, sampler: &Sampler, uv: Vec2, layer: real) -> Vec4 {
+        sampler.sample(self.layer(layer), uv)
+    }
+
+    pub fn sample_lod(&self, sampler: &Sampler, uv: Vec2, layer: real, lod: real) -> Vec4 {
+        sampler.sample_lod(self.layer(layer), uv, lod)
+    }
+
+    pub fn sample_grad(&self, sampler: &Sampler, uv: Vec2, layer: real, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        sampler.sample_grad(self.layer(layer), uv, ddx, ddy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_arrays_are_rejected() {
+        let sheet = Texture::from_rgba8(2, 2, vec![0; 16]).unwrap();
+
+        assert!(TextureArray::from_layers(Vec::new()).is_err());
+        assert!(TextureArray::from_grid(&sheet, 0, 1).is_err());
+        assert!(TextureArray::from_grid(&sheet, 1, 0).is_err());
+
+        let array = TextureArray::from_grid(&sheet, 2, 1).unwrap();
+        assert_eq!(array.len(), 2);
+        assert!(!array.is_empty());
+    }
+}
@@ -1,5 +1,11 @@
+use crate::hiz::*;
+use crate::oit::*;
+use crate::utils::*;
+
 pub struct Buffer {
     data: Vec<f32>,
+    hiz: DepthPyramid,
+    fragments: FragmentLists,
     pub width: usize,
     pub height: usize,
     pub depth: usize
@@ -9,6 +15,8 @@ impl Buffer {
     pub fn new(width: usize, height: usize, depth: usize) -> Buffer {
         Buffer {
             data: vec![0.0; width * height * depth],
+            hiz: DepthPyramid::new(width, height),
+            fragments: FragmentLists::new(width * height),
             width, height, depth
         }
     }
@@ -17,11 +25,82 @@ impl Buffer {
         // self.data = vec![0.0; self.width * self.height * self.depth];
 
         for i in 0..self.data.len() {
-            self.data[i] = std::f32::NEG_INFINITY;
+            self.data[i] = f32::NEG_INFINITY;
         }
+        self.hiz.clear();
+        self.fragments.clear();
+    }
+
+    // Raw pixels, `depth` floats each, e.g. for comparing against golden images
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn hiz(&self) -> &DepthPyramid {
+        &self.hiz
+    }
+
+    pub fn update_hiz(&mut self) {
+        if self.depth < 4 {
+            return;
+        }
+
+        let (data, width, depth) = (&self.data, self.width, self.depth);
+        self.hiz.update(width, self.height, |x, y| data[(y * width + x) * depth + 3]);
+    }
+
+    // Occlusion test for a box in object space, projected by `matrix` the same way `Shader::draw`
+    // projects vertices. False only if the box is off-screen or entirely behind what's drawn.
+    pub fn aabb_visible(&self, matrix: Mat4, min: Vec3, max: Vec3) -> bool {
+        let (screen_min, screen_max) = match project_aabb(matrix, min, max, self.width, self.height) {
+            Some(bounds) => bounds,
+            None => return true,
+        };
+
+        let x0 = screen_min.x.max(0.0) as usize;
+        let y0 = screen_min.y.max(0.0) as usize;
+        let x1 = screen_max.x.clamp(0.0, self.width as real) as usize;
+        let y1 = screen_max.y.clamp(0.0, self.height as real) as usize;
+
+        self.hiz.rect_visible(x0, y0, x1, y1, to_f32(screen_max.z))
     }
 
-    pub fn fill_window_buffer(&self, window_buffer: &mut Vec<u32>, depth: bool) -> Result<(), &'static str> {
+    pub fn fragments(&self) -> &FragmentLists {
+        &self.fragments
+    }
+
+    // Stores a transparent fragment until `resolve_fragments`, nothing is depth tested here
+    pub fn push_fragment(&mut self, x: usize, y: usize, color: [f32; 4], depth: f32) {
+        self.fragments.push(y * self.width + x, color, depth);
+    }
+
+    // Blends every pixel's transparent fragments back to front over its color, using their alpha.
+    // Depth is left as the opaque geometry wrote it.
+    pub fn resolve_fragments(&mut self) -> Result<(), &'static str> {
+        if self.depth < 3 {
+            return Err("Buffer's depth is less than 3!");
+        }
+
+        let mut sorted = Vec::new();
+        for (pixel, destination) in self.data.chunks_exact_mut(self.depth).enumerate() {
+            self.fragments.sorted(pixel, &mut sorted);
+
+            for (_, color) in sorted.iter() {
+                let alpha = color[3].clamp(0.0, 1.0);
+
+                for channel in 0..3 {
+                    // Cleared pixels hold no color yet
+                    let below = if destination[channel].is_finite() { destination[channel] } else { 0.0 };
+                    destination[channel] = color[channel] * alpha + below * (1.0 - alpha);
+                }
+            }
+        }
+
+        self.fragments.clear();
+        Ok(())
+    }
+
+    pub fn fill_window_buffer(&self, window_buffer: &mut [u32], depth: bool) -> Result<(), &'static str> {
         if self.depth < 3 {
             Err("Buffer's depth is less than 3!")
         } else {
@@ -47,15 +126,66 @@ impl Buffer {
         }
     }
 
+    pub fn get_pixel(&self, x: usize, y: usize) -> &[f32] {
+        let start = (y * self.width + x) * self.depth;
+        &self.data[start..start + self.depth]
+    }
+
+    pub fn get_depth(&self, x: usize, y: usize) -> f32 {
+        self.data[(y * self.width + x) * self.depth + 3]
+    }
+
+    // Depths of `N` horizontally adjacent pixels from (x, y) on. Only the first `count` are read,
+    // the others are infinite so no depth test passes there.
+    pub(crate) fn get_depths<const N: usize>(&self, x: usize, y: usize, count: usize) -> [f32; N] {
+        let mut depths = [f32::INFINITY; N];
+        let start = (y * self.width + x) * self.depth;
+        let pixels = &self.data[start..start + count.min(N) * self.depth];
+
+        for (depth, pixel) in depths.iter_mut().zip(pixels.chunks_exact(self.depth)) {
+            *depth = pixel[3];
+        }
+
+        depths
+    }
+
+    pub fn depth_test(&self, x: usize, y: usize, depth: f32) -> bool {
+        self.get_depth(x, y) < depth
+    }
+
+    // Color only, without depth test, e.g. to fill the background
+    pub fn set_color(&mut self, x: usize, y: usize, color: [f32; 3]) {
+        let start = (y * self.width + x) * self.depth;
+        let channels = self.depth.min(3);
+        self.data[start..start + channels].copy_from_slice(&color[..channels]);
+    }
+
+    // Writes the pixels from (x, y) on that are set in `mask`, without testing their depth
+    pub(crate) fn set_pixels<const N: usize>(&mut self, x: usize, y: usize, pixels: &[[f32; 4]; N], mask: [bool; N]) {
+        let start = (y * self.width + x) * 4;
+        let count = N - mask.iter().rev().take_while(|&&mask| !mask).count();
+        let destination = &mut self.data[start..start + count * 4];
+
+        for ((destination, pixel), mask) in destination.chunks_exact_mut(4).zip(pixels).zip(mask) {
+            if mask {
+                destination.copy_from_slice(pixel);
+            }
+        }
+
+        if count > 0 {
+            self.hiz.mark_dirty(x, y);
+            self.hiz.mark_dirty(x + count - 1, y);
+        }
+    }
+
     pub fn set_pixel(&mut self, x: usize, y: usize, value: &[f32]) -> Result<(), &'static str> {
         if self.depth != value.len() {
             Err("Buffer's pixel boundary will overflow!")
         } else {
-            // TODO: Dont run fragment shader
-            if self.data[(y * self.width + x) * self.depth + 3] < value[3] {
-                for i in 0..self.depth {
-                    self.data[(y * self.width + x) * self.depth + i] = value[i];
-                }
+            if self.depth_test(x, y, value[3]) {
+                let start = (y * self.width + x) * self.depth;
+                self.data[start..start + self.depth].copy_from_slice(value);
+                self.hiz.mark_dirty(x, y);
             }
 
             Ok(())
@@ -0,0 +1,99 @@
+use crate::utils::*;
+
+// Upper bound for `Shader::CLIP_DISTANCES`
+pub const MAX_CLIP_DISTANCES: usize = 8;
+pub type ClipDistances = [real; MAX_CLIP_DISTANCES];
+
+// Vertex of a polygon being clipped. Positions are kept in clip space, barycentric coordinates
+// relative to the triangle being clipped recover the varyings with `Interpolate`.
+#[derive(Clone, Copy, Debug)]
+pub struct ClipVertex {
+    pub position: Vec4,
+    pub weights: Vec3,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ClipPlane {
+    // Signed distances at the triangle's three vertices, e.g. user clip distances
+    Distances(Vec3),
+    // Keeps positions where `sign * position[axis] <= bound`
+    Bound { axis: usize, sign: real, bound: real },
+}
+
+impl ClipPlane {
+    fn distance(&self, vertex: &ClipVertex) -> real {
+        match *self {
+            ClipPlane::Distances(distances) => vertex.weights.dot(distances),
+            ClipPlane::Bound { axis, sign, bound } => bound - sign * vertex.position[axis],
+        }
+    }
+}
+
+pub fn triangle_polygon(positions: &[Vec4; 3]) -> Vec<ClipVertex> {
+    positions.iter()
+        .zip([Vec3::X, Vec3::Y, Vec3::Z])
+        .map(|(&position, weights)| ClipVertex { position, weights })
+        .collect()
+}
+
+// Sutherland-Hodgman, points with a non-negative distance are kept. New vertices are
+// interpolated between the ends of the edge they cut, so huge vertices elsewhere in the polygon
+// don't cost any precision, and are put exactly on the plane when it's a bound.
+pub fn clip_polygon(polygon: &[ClipVertex], plane: ClipPlane) -> Vec<ClipVertex> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + 1) % polygon.len()];
+        let da = plane.distance(a);
+        let db = plane.distance(b);
+
+        if da >= 0.0 {
+            clipped.push(*a);
+        }
+
+        if (da >= 0.0) != (db >= 0.0) {
+            let t = da / (da - db);
+            let mut vertex = ClipVertex {
+                position: a.position.lerp(b.position, t),
+                weights: a.weights.lerp(b.weights, t),
+            };
+
+            if let ClipPlane::Bound { axis, sign, bound } = plane {
+                vertex.position[axis] = sign * bound;
+            }
+
+            clipped.push(vertex);
+        }
+    }
+
+    clipped
+}
+
+// Clips against every plane in turn, empty as soon as nothing with an area is left
+pub fn clip_to_planes(polygon: Vec<ClipVertex>, planes: impl IntoIterator<Item = ClipPlane>) -> Vec<ClipVertex> {
+    let mut polygon = polygon;
+
+    for plane in planes {
+        polygon = clip_polygon(&polygon, plane);
+        if polygon.len() < 3 {
+            return Vec::new();
+        }
+    }
+
+    polygon
+}
+
+// The first `count` user clip distances of the triangle's vertices, one plane each
+pub fn clip_distance_planes(distances: [&ClipDistances; 3], count: usize) -> Vec<Vec3> {
+    (0..count.min(MAX_CLIP_DISTANCES))
+        .map(|i| Vec3::new(distances[0][i], distances[1][i], distances[2][i]))
+        .collect()
+}
+
+pub fn inside_guard_band(positions: &[Vec4; 3], guard_band: real) -> bool {
+    positions.iter().all(|p| p.x.abs() <= guard_band && p.y.abs() <= guard_band)
+}
+
+pub fn guard_band_planes(guard_band: real) -> [ClipPlane; 4] {
+    [(0, 1.0), (0, -1.0), (1, 1.0), (1, -1.0)].map(|(axis, sign)| ClipPlane::Bound { axis, sign, bound: guard_band })
+}
@@ -0,0 +1,230 @@
+use crate::utils::*;
+use crate::buffer::*;
+use crate::texture::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+pub const CUBE_FACES: [CubeFace; 6] = [
+    CubeFace::PositiveX,
+    CubeFace::NegativeX,
+    CubeFace::PositiveY,
+    CubeFace::NegativeY,
+    CubeFace::PositiveZ,
+    CubeFace::NegativeZ,
+];
+
+impl CubeFace {
+    // Direction through the face's center, then the directions texture coordinates
+    // u and v go along (OpenGL's layout)
+    pub fn axes(self) -> (Vec3, Vec3, Vec3) {
+        match self {
+            CubeFace::PositiveX => (Vec3::X, -Vec3::Z, -Vec3::Y),
+            CubeFace::NegativeX => (-Vec3::X, Vec3::Z, -Vec3::Y),
+            CubeFace::PositiveY => (Vec3::Y, Vec3::X, Vec3::Z),
+            CubeFace::NegativeY => (-Vec3::Y, Vec3::X, -Vec3::Z),
+            CubeFace::PositiveZ => (Vec3::Z, Vec3::X, -Vec3::Y),
+            CubeFace::NegativeZ => (-Vec3::Z, -Vec3::X, -Vec3::Y),
+        }
+    }
+
+    // Face a direction points at and the texture coordinates it hits there
+    pub fn from_direction(direction: Vec3) -> (CubeFace, Vec2) {
+        let abs = direction.abs();
+        let face = if abs.x >= abs.y && abs.x >= abs.z {
+            if direction.x >= 0.0 { CubeFace::PositiveX } else { CubeFace::NegativeX }
+        } else if abs.y >= abs.z {
+            if direction.y >= 0.0 { CubeFace::PositiveY } else { CubeFace::NegativeY }
+        } else if direction.z >= 0.0 {
+            CubeFace::PositiveZ
+        } else {
+            CubeFace::NegativeZ
+        };
+
+        let (major, u, v) = face.axes();
+        let distance = direction.dot(major);
+        let uv = Vec2::new(direction.dot(u), direction.dot(v)) / distance;
+
+        (face, (uv + Vec2::ONE) * 0.5)
+    }
+
+    pub fn direction(self, uv: Vec2) -> Vec3 {
+        let (major, u, v) = self.axes();
+        let uv = uv * 2.0 - Vec2::ONE;
+
+        major + u * uv.x + v * uv.y
+    }
+}
+
+// Six square textures of the same size, in `CUBE_FACES` order
+pub struct CubeMap {
+    pub faces: [Texture; 6],
+}
+
+impl CubeMap {
+    pub fn from_faces(faces: [Texture; 6]) -> Result<CubeMap, &'static str> {
+        let size = faces[0].width;
+
+        if size == 0 || faces.iter().any(|face| face.width != size || face.height != size) {
+            Err("Cube map faces must be square and of the same size!")
+        } else {
+            Ok(CubeMap { faces })
+        }
+    }
+
+    // Horizontal (4x3 faces) or vertical (3x4 faces) cross:
+    //
+    //      +Y                 +Y
+    //   -X +Z +X -Z        -X +Z +X
+    //      -Y                 -Y
+    //                         -Z (upside down)
+    pub fn from_cross(cross: &Texture) -> Result<CubeMap, &'static str> {
+        let (width, height) = (cross.width, cross.height);
+
+        let (size, negative_z) = if width * 3 == height * 4 {
+            (width / 4, (3, 1))
+        } else if width * 4 == height * 3 {
+            (width / 3, (1, 3))
+        } else {
+            return Err("Image isn't laid out as a cube map cross!");
+        };
+
+        let face = |(x, y): (usize, usize)| cross.region(x * size, y * size, size, size);
+        let mut negative_z_face = face(negative_z)?;
+        if negative_z == (1, 3) {
+            negative_z_face = negative_z_face.rotated_180();
+        }
+
+        CubeMap::from_faces([
+            face((2, 1))?,
+            face((0, 1))?,
+            face((1, 0))?,
+            face((1, 2))?,
+            face((1, 1))?,
+            negative_z_face,
+        ])
+    }
+
+    pub fn generate_mipmaps(&mut self) {
+        for face in self.faces.iter_mut() {
+            face.generate_mipmaps();
+        }
+    }
+
+    pub fn levels(&self) -> usize {
+        self.faces[0].levels()
+    }
+
+    // Full resolution only. Wrap modes don't apply, bilinear filtering reads across face edges.
+    pub fn sample(&self, sampler: &Sampler, direction: Vec3) -> Vec4 {
+        self.sample_level(sampler.filter, direction, 0)
+    }
+
+    // Without `lod_bias`, as for the other textures it only applies to derivatives
+    pub fn sample_lod(&self, sampler: &Sampler, direction: Vec3, lod: real) -> Vec4 {
+        sampler.mip_filter.sample(lod, self.levels(), |level| self.sample_level(sampler.filter, direction, level))
+    }
+
+    fn sample_level(&self, filter: Filter, direction: Vec3, level: usize) -> Vec4 {
+        if !direction.is_finite() || direction == Vec3::ZERO {
+            return Vec4::ZERO;
+        }
+
+        let (face, uv) = CubeFace::from_direction(direction);
+        let position = uv * self.faces[face as usize].level(level).width as real;
+
+        match filter {
+            Filter::Nearest => self.texel(face, level, position.x.floor() as i64, position.y.floor() as i64),
+            Filter::Bilinear => {
+                let position = position - Vec2::splat(0.5);
+                let corner = position.floor();
+                let t = position - corner;
+                let (x, y) = (corner.x as i64, corner.y as i64);
+
+                let top = self.texel(face, level, x, y).lerp(self.texel(face, level, x + 1, y), t.x);
+                let bottom = self.texel(face, level, x, y + 1).lerp(self.texel(face, level, x + 1, y + 1), t.x);
+                top.lerp(bottom, t.y)
+            }
+        }
+    }
+
+    // Texels past a face's edge are read from the face next to it
+    fn texel(&self, face: CubeFace, level: usize, x: i64, y: i64) -> Vec4 {
+        let texture = self.faces[face as usize].level(level);
+        let size = texture.width as i64;
+
+        if (0..size).contains(&x) && (0..size).contains(&y) {
+            return texture.texel(x as usize, y as usize);
+        }
+
+        let center = (Vec2::new(x as real, y as real) + Vec2::splat(0.5)) / size as real;
+        let (face, uv) = CubeFace::from_direction(face.direction(center));
+        let texture = self.faces[face as usize].level(level);
+        let position = (uv * size as real).floor();
+
+        texture.texel(
+            (position.x.max(0.0) as usize).min(texture.width - 1),
+            (position.y.max(0.0) as usize).min(texture.height - 1)
+        )
+    }
+}
+
+// Fills pixels nothing has been drawn to since the last clear with the cube map seen from the
+// camera, so the skybox can be drawn before or after the scene. `inverse_view_projection` takes
+// normalized device coordinates back to world space, translation only shifts the sky.
+// Unlike the rasterizer, this divides by w. Without the divide every pixel would look along the
+// same direction and the sky would be a single color. Both agree for projections keeping w at 1,
+// with a perspective one the sky follows its field of view while geometry is drawn undivided.
+pub fn draw_skybox(buffer: &mut Buffer, cube_map: &CubeMap, sampler: &Sampler, inverse_view_projection: Mat4) {
+    if buffer.depth < 4 {
+        return;
+    }
+
+    for y in 0..buffer.height {
+        for x in 0..buffer.width {
+            if buffer.get_depth(x, y) != f32::NEG_INFINITY {
+                continue;
+            }
+
+            let screen = buffer_to_screen_space(x, y, buffer.width, buffer.height);
+            let near = inverse_view_projection * Vec4::new(screen.x, screen.y, 0.0, 1.0);
+            let far = inverse_view_projection * Vec4::new(screen.x, screen.y, 1.0, 1.0);
+            let direction = far.truncate() / far.w - near.truncate() / near.w;
+
+            let color = cube_map.sample(sampler, direction);
+            buffer.set_color(x, y, [to_f32(color.x), to_f32(color.y), to_f32(color.z)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_lods_ignore_the_bias() {
+        // Level 0 is a checkerboard of 0 and 1, level 1 averages to 0.5
+        let face = |_| {
+            let checker = (0..16).flat_map(|i| [((i + i / 4) % 2) as f32; 4]).collect();
+            Texture::from_rgba32f(4, 4, checker).unwrap()
+        };
+        let mut cube_map = CubeMap::from_faces([0; 6].map(face)).unwrap();
+        cube_map.generate_mipmaps();
+
+        let sampler = Sampler::new(Filter::Nearest, Wrap::Clamp);
+        let biased = Sampler { lod_bias: 1.0, ..sampler };
+        let direction = Vec3::new(1.0, 0.1, 0.2);
+
+        for lod in [0.0, 0.5, 1.0] {
+            assert_eq!(cube_map.sample_lod(&biased, direction, lod), cube_map.sample_lod(&sampler, direction, lod));
+        }
+        assert_eq!(cube_map.sample_lod(&sampler, direction, 1.0).x, 0.5);
+    }
+}
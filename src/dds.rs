@@ -0,0 +1,187 @@
+use crate::formats::*;
+use crate::texture::*;
+
+const HEADER_SIZE: usize = 128;
+const DX10_HEADER_SIZE: usize = 20;
+
+const FLAG_MIPMAP_COUNT: u32 = 0x20000;
+const PIXEL_FORMAT_FOURCC: u32 = 0x4;
+const PIXEL_FORMAT_RGB: u32 = 0x40;
+const CAPS2_CUBEMAP: u32 = 0x200;
+const CAPS2_VOLUME: u32 = 0x200000;
+const MISC_TEXTURECUBE: u32 = 0x4;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn dxgi_format(format: u32) -> Result<TextureFormat, &'static str> {
+    Ok(match format {
+        28 | 29 => TextureFormat::Rgba8,
+        87 | 91 => TextureFormat::Bgra8,
+        70..=72 => TextureFormat::Bc1,
+        73..=75 => TextureFormat::Bc2,
+        76..=78 => TextureFormat::Bc3,
+        79 | 80 => TextureFormat::Bc4,
+        81 => TextureFormat::Bc4Signed,
+        82 | 83 => TextureFormat::Bc5,
+        84 => TextureFormat::Bc5Signed,
+        94 | 95 => TextureFormat::Bc6h,
+        96 => TextureFormat::Bc6hSigned,
+        97..=99 => TextureFormat::Bc7,
+        _ => return Err("Unsupported DXGI format in DDS file!"),
+    })
+}
+
+fn fourcc_format(fourcc: &[u8]) -> Result<TextureFormat, &'static str> {
+    Ok(match fourcc {
+        b"DXT1" => TextureFormat::Bc1,
+        b"DXT2" | b"DXT3" => TextureFormat::Bc2,
+        b"DXT4" | b"DXT5" => TextureFormat::Bc3,
+        b"ATI1" | b"BC4U" => TextureFormat::Bc4,
+        b"BC4S" => TextureFormat::Bc4Signed,
+        b"ATI2" | b"BC5U" => TextureFormat::Bc5,
+        b"BC5S" => TextureFormat::Bc5Signed,
+        _ => return Err("Unsupported FourCC in DDS file!"),
+    })
+}
+
+// 2D DDS textures along with the mip levels stored in the file. Cube maps, arrays and
+// volumes aren't supported.
+pub fn from_dds(data: &[u8]) -> Result<Texture, &'static str> {
+    if data.len() < HEADER_SIZE || &data[..4] != b"DDS " || read_u32(data, 4) != 124 {
+        return Err("Not a DDS file!");
+    }
+
+    let flags = read_u32(data, 8);
+    let height = read_u32(data, 12) as usize;
+    let width = read_u32(data, 16) as usize;
+    let depth = read_u32(data, 24);
+    let levels = if flags & FLAG_MIPMAP_COUNT != 0 { read_u32(data, 28).max(1) as usize } else { 1 };
+    let pixel_format_flags = read_u32(data, 80);
+    let fourcc = &data[84..88];
+    let caps2 = read_u32(data, 112);
+
+    if caps2 & (CAPS2_CUBEMAP | CAPS2_VOLUME) != 0 || depth > 1 {
+        return Err("Only 2D DDS textures are supported!");
+    }
+    if width == 0 || height == 0 {
+        return Err("DDS texture has no texels!");
+    }
+
+    let (format, mut offset) = if pixel_format_flags & PIXEL_FORMAT_FOURCC != 0 && fourcc == b"DX10" {
+        if data.len() < HEADER_SIZE + DX10_HEADER_SIZE {
+            return Err("DDS file is cut short!");
+        }
+
+        let dimension = read_u32(data, HEADER_SIZE + 4);
+        let misc_flags = read_u32(data, HEADER_SIZE + 8);
+        let array_size = read_u32(data, HEADER_SIZE + 12);
+        // Texture2D, or Texture1D as a texture 1 texel high
+        if !(2..=3).contains(&dimension) || misc_flags & MISC_TEXTURECUBE != 0 || array_size > 1 {
+            return Err("Only 2D DDS textures are supported!");
+        }
+
+        (dxgi_format(read_u32(data, HEADER_SIZE))?, HEADER_SIZE + DX10_HEADER_SIZE)
+    } else if pixel_format_flags & PIXEL_FORMAT_FOURCC != 0 {
+        (fourcc_format(fourcc)?, HEADER_SIZE)
+    } else if pixel_format_flags & PIXEL_FORMAT_RGB != 0 && read_u32(data, 88) == 32 {
+        // Channel masks of the two 32 bit layouts decoded here
+        match (read_u32(data, 92), read_u32(data, 100)) {
+            (0xff, 0xff0000) => (TextureFormat::Rgba8, HEADER_SIZE),
+            (0xff0000, 0xff) => (TextureFormat::Bgra8, HEADER_SIZE),
+            _ => return Err("Unsupported pixel format in DDS file!"),
+        }
+    } else {
+        return Err("Unsupported pixel format in DDS file!");
+    };
+
+    // Some writers count more levels than the size can halve down to
+    let levels = levels.min(width.max(height).ilog2() as usize + 1);
+    let mut level_data = Vec::with_capacity(levels);
+    for level in 0..levels {
+        let size = format.data_size((width >> level).max(1), (height >> level).max(1))?;
+        let end = offset.checked_add(size).ok_or("DDS file is cut short!")?;
+        let bytes = data.get(offset..end).ok_or("DDS file is cut short!")?;
+
+        level_data.push(bytes);
+        offset = end;
+    }
+
+    format.decode_levels(width, height, &level_data)
+}
+
+pub fn open_dds(path: impl AsRef<std::path::Path>) -> Result<Texture, &'static str> {
+    from_dds(&std::fs::read(path).map_err(|_| "Couldn't open the DDS file!")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+
+    fn header(width: u32, height: u32, levels: u32) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        data[..4].copy_from_slice(b"DDS ");
+        for (offset, value) in [(4, 124), (8, FLAG_MIPMAP_COUNT), (12, height), (16, width), (28, levels), (76, 32)] {
+            data[offset..offset + 4].copy_from_slice(&u32::to_le_bytes(value));
+        }
+
+        data
+    }
+
+    // 32 bit BGRA, the channel masks of the usual B8G8R8A8 layout
+    fn bgra8(width: u32, height: u32, levels: u32) -> Vec<u8> {
+        let mut data = header(width, height, levels);
+        for (offset, value) in [(80, PIXEL_FORMAT_RGB), (88, 32), (92, 0xff0000), (96, 0xff00), (100, 0xff), (104, 0xff000000)] {
+            data[offset..offset + 4].copy_from_slice(&u32::to_le_bytes(value));
+        }
+
+        data
+    }
+
+    #[test]
+    fn bgra8_with_mips() {
+        let mut data = bgra8(2, 2, 2);
+        data.extend([
+            [10, 20, 30, 255], [40, 50, 60, 128],
+            [70, 80, 90, 0], [100, 110, 120, 255],
+            [1, 2, 3, 4],
+        ].concat());
+
+        let texture = from_dds(&data).unwrap();
+        assert_eq!((texture.width, texture.height, texture.levels()), (2, 2, 2));
+        assert_eq!(texture.texel(0, 0), Vec4::new(30.0, 20.0, 10.0, 255.0) / 255.0);
+        assert_eq!(texture.texel(1, 0), Vec4::new(60.0, 50.0, 40.0, 128.0) / 255.0);
+        assert_eq!(texture.texel(1, 1), Vec4::new(120.0, 110.0, 100.0, 255.0) / 255.0);
+        assert_eq!(texture.level(1).texel(0, 0), Vec4::new(3.0, 2.0, 1.0, 4.0) / 255.0);
+
+        assert!(from_dds(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn dxt1() {
+        let mut data = header(4, 4, 1);
+        data[80..84].copy_from_slice(&u32::to_le_bytes(PIXEL_FORMAT_FOURCC));
+        data[84..88].copy_from_slice(b"DXT1");
+        // Red and blue endpoints, the first row going through all four colors
+        data.extend([0x00, 0xf8, 0x1f, 0x00, 0b11_10_01_00, 0, 0, 0]);
+
+        let texture = from_dds(&data).unwrap();
+        assert_eq!(texture.texel(0, 0), Vec4::new(255.0, 0.0, 0.0, 255.0) / 255.0);
+        assert_eq!(texture.texel(2, 0), Vec4::new(170.0, 0.0, 85.0, 255.0) / 255.0);
+        assert_eq!(texture.texel(3, 3), Vec4::new(255.0, 0.0, 0.0, 255.0) / 255.0);
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        let mut data = header(0xffffffff, 0xffffffff, 1);
+        data[80..84].copy_from_slice(&u32::to_le_bytes(PIXEL_FORMAT_FOURCC));
+        data[84..88].copy_from_slice(b"DX10");
+        data.extend([98, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend([0; 16]);
+        assert!(from_dds(&data).is_err());
+
+        assert!(from_dds(&bgra8(0, 4, 1)).is_err());
+    }
+}
@@ -0,0 +1,1368 @@
+use crate::texture::*;
+
+// How texels are stored in DDS and KTX2 files. Block compressed formats (BC and ETC2/EAC) store
+// 4x4 texels per block and are decoded to plain textures when loaded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureFormat {
+    Rgba8,
+    Bgra8,
+    // BC1 with alpha ignored, texels the 3 color mode makes transparent are black
+    Bc1Rgb,
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc4Signed,
+    Bc5,
+    Bc5Signed,
+    // Half floats
+    Bc6h,
+    Bc6hSigned,
+    Bc7,
+    Etc2Rgb,
+    // One bit alpha (punch-through)
+    Etc2RgbA1,
+    Etc2Rgba,
+    EacR11,
+    EacR11Signed,
+    EacRg11,
+    EacRg11Signed,
+}
+
+impl TextureFormat {
+    pub fn is_compressed(self) -> bool {
+        !matches!(self, TextureFormat::Rgba8 | TextureFormat::Bgra8)
+    }
+
+    // Bytes per 4x4 block, or per texel if uncompressed
+    fn block_bytes(self) -> usize {
+        match self {
+            TextureFormat::Rgba8 | TextureFormat::Bgra8 => 4,
+            TextureFormat::Bc1Rgb
+            | TextureFormat::Bc1
+            | TextureFormat::Bc4
+            | TextureFormat::Bc4Signed
+            | TextureFormat::Etc2Rgb
+            | TextureFormat::Etc2RgbA1
+            | TextureFormat::EacR11
+            | TextureFormat::EacR11Signed => 8,
+            _ => 16,
+        }
+    }
+
+    // Bytes an image of this size takes up, an error if that doesn't fit in memory
+    pub fn data_size(self, width: usize, height: usize) -> Result<usize, &'static str> {
+        let (columns, rows) = if self.is_compressed() {
+            (width.div_ceil(4), height.div_ceil(4))
+        } else {
+            (width, height)
+        };
+
+        // The decoded texture needs 4 floats per texel
+        let texels = width.checked_mul(height).and_then(|texels| texels.checked_mul(16));
+        let size = columns.checked_mul(rows).and_then(|blocks| blocks.checked_mul(self.block_bytes()));
+
+        match (texels, size) {
+            (Some(texels), Some(size)) if texels <= isize::MAX as usize => Ok(size),
+            _ => Err("Texture is too large!"),
+        }
+    }
+
+    // Formats without more than 8 bits of precision per channel decode to 8 bit textures,
+    // the rest to float ones
+    pub fn decode(self, width: usize, height: usize, data: &[u8]) -> Result<Texture, &'static str> {
+        if data.len() < self.data_size(width, height)? {
+            return Err("Not enough texture data for its size and format!");
+        }
+
+        let texels = match self {
+            TextureFormat::Rgba8 => return Texture::from_rgba8(width, height, data[..width * height * 4].to_vec()),
+            TextureFormat::Bgra8 => return Texture::from_rgba8(width, height, data[..width * height * 4]
+                .chunks_exact(4)
+                .flat_map(|texel| [texel[2], texel[1], texel[0], texel[3]])
+                .collect()),
+            TextureFormat::Bc1Rgb => decode_blocks(width, height, data, 8, |block| decode_bc1(block, false)),
+            TextureFormat::Bc1 => decode_blocks(width, height, data, 8, |block| decode_bc1(block, true)),
+            TextureFormat::Bc2 => decode_blocks(width, height, data, 16, decode_bc2),
+            TextureFormat::Bc3 => decode_blocks(width, height, data, 16, decode_bc3),
+            TextureFormat::Bc4 => decode_blocks(width, height, data, 8, |block| decode_bc4(block, false)),
+            TextureFormat::Bc4Signed => decode_blocks(width, height, data, 8, |block| decode_bc4(block, true)),
+            TextureFormat::Bc5 => decode_blocks(width, height, data, 16, |block| decode_bc5(block, false)),
+            TextureFormat::Bc5Signed => decode_blocks(width, height, data, 16, |block| decode_bc5(block, true)),
+            TextureFormat::Bc6h => decode_blocks(width, height, data, 16, |block| decode_bc6h(block, false)),
+            TextureFormat::Bc6hSigned => decode_blocks(width, height, data, 16, |block| decode_bc6h(block, true)),
+            TextureFormat::Bc7 => decode_blocks(width, height, data, 16, decode_bc7),
+            TextureFormat::Etc2Rgb => decode_blocks(width, height, data, 8, |block| decode_etc2(block, false)),
+            TextureFormat::Etc2RgbA1 => decode_blocks(width, height, data, 8, |block| decode_etc2(block, true)),
+            TextureFormat::Etc2Rgba => decode_blocks(width, height, data, 16, decode_etc2_eac),
+            TextureFormat::EacR11 => decode_blocks(width, height, data, 8, |block| decode_eac_r11(block, false)),
+            TextureFormat::EacR11Signed => decode_blocks(width, height, data, 8, |block| decode_eac_r11(block, true)),
+            TextureFormat::EacRg11 => decode_blocks(width, height, data, 16, |block| decode_eac_rg11(block, false)),
+            TextureFormat::EacRg11Signed => decode_blocks(width, height, data, 16, |block| decode_eac_rg11(block, true)),
+        };
+
+        match self {
+            TextureFormat::Bc1Rgb
+            | TextureFormat::Bc1
+            | TextureFormat::Bc2
+            | TextureFormat::Bc3
+            | TextureFormat::Bc7
+            | TextureFormat::Etc2Rgb
+            | TextureFormat::Etc2RgbA1
+            | TextureFormat::Etc2Rgba => Texture::from_rgba8(width, height, texels.iter()
+                .map(|&channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8)
+                .collect()),
+            _ => Texture::from_rgba32f(width, height, texels),
+        }
+    }
+
+    // Level 0 first, each level after that half the size of the one before
+    pub fn decode_levels(self, width: usize, height: usize, levels: &[&[u8]]) -> Result<Texture, &'static str> {
+        let (first, rest) = levels.split_first().ok_or("No texture levels to decode!")?;
+        let mut texture = self.decode(width, height, first)?;
+
+        let mut mips = Vec::with_capacity(rest.len());
+        for (level, data) in rest.iter().enumerate() {
+            let shift = level as u32 + 1;
+            let size = |size: usize| size.checked_shr(shift).unwrap_or(0).max(1);
+            mips.push(self.decode(size(width), size(height), data)?);
+        }
+
+        texture.set_mips(mips)?;
+        Ok(texture)
+    }
+}
+
+type Block = [[f32; 4]; 16];
+
+// Texels of the blocks row by row, the parts of edge blocks outside the image are dropped
+fn decode_blocks(width: usize, height: usize, data: &[u8], block_bytes: usize, decode: impl Fn(&[u8]) -> Block) -> Vec<f32> {
+    let mut texels = vec![0.0; width * height * 4];
+    let blocks_x = width.div_ceil(4);
+
+    for (i, block) in data.chunks_exact(block_bytes).take(blocks_x * height.div_ceil(4)).enumerate() {
+        let decoded = decode(block);
+        let (block_x, block_y) = (i % blocks_x * 4, i / blocks_x * 4);
+
+        for (j, texel) in decoded.iter().enumerate() {
+            let (x, y) = (block_x + j % 4, block_y + j / 4);
+            if x < width && y < height {
+                let start = (y * width + x) * 4;
+                texels[start..start + 4].copy_from_slice(texel);
+            }
+        }
+    }
+
+    texels
+}
+
+fn rgba8(color: [u32; 4]) -> [f32; 4] {
+    color.map(|channel| channel as f32 / 255.0)
+}
+
+fn read_u16(data: &[u8]) -> u32 {
+    u16::from_le_bytes([data[0], data[1]]) as u32
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+}
+
+// Little endian bit stream, from the lowest bit of the first byte
+struct Bits {
+    value: u128,
+}
+
+impl Bits {
+    fn new(block: &[u8]) -> Bits {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&block[..16]);
+        Bits { value: u128::from_le_bytes(bytes) }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let bits = (self.value & ((1 << count) - 1)) as u32;
+        self.value >>= count;
+        bits
+    }
+}
+
+// BC1 to BC3
+
+fn rgb565(color: u32) -> [u32; 3] {
+    let (r, g, b) = (color >> 11 & 31, color >> 5 & 63, color & 31);
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+// BC2 and BC3 always use 4 colors, BC1 switches to 3 and transparent black when the endpoints
+// are in increasing order
+fn decode_bc1_colors(block: &[u8], three_colors: bool, alpha: bool) -> Block {
+    let (color0, color1) = (read_u16(block), read_u16(&block[2..]));
+    let (c0, c1) = (rgb565(color0), rgb565(color1));
+
+    let mut colors = [[c0[0], c0[1], c0[2], 255], [c1[0], c1[1], c1[2], 255], [0; 4], [0, 0, 0, 255]];
+    if color0 > color1 || !three_colors {
+        for channel in 0..3 {
+            colors[2][channel] = (2 * c0[channel] + c1[channel] + 1) / 3;
+            colors[3][channel] = (c0[channel] + 2 * c1[channel] + 1) / 3;
+        }
+        colors[2][3] = 255;
+    } else {
+        for channel in 0..3 {
+            colors[2][channel] = (c0[channel] + c1[channel]).div_ceil(2);
+        }
+        colors[2][3] = 255;
+        colors[3][3] = if alpha { 0 } else { 255 };
+    }
+
+    let indices = read_u32(&block[4..]);
+    std::array::from_fn(|i| rgba8(colors[(indices >> (2 * i) & 3) as usize]))
+}
+
+fn decode_bc1(block: &[u8], alpha: bool) -> Block {
+    decode_bc1_colors(block, true, alpha)
+}
+
+fn decode_bc2(block: &[u8]) -> Block {
+    let mut texels = decode_bc1_colors(&block[8..], false, false);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = (block[i / 2] >> (4 * (i % 2)) & 15) as f32 / 15.0;
+    }
+
+    texels
+}
+
+fn decode_bc3(block: &[u8]) -> Block {
+    let mut texels = decode_bc1_colors(&block[8..], false, false);
+    for (texel, alpha) in texels.iter_mut().zip(decode_bc4_channel(block, false)) {
+        texel[3] = alpha;
+    }
+
+    texels
+}
+
+// BC4 and BC5
+
+// One channel from 8 bytes, [0, 1] or [-1, 1] if signed
+fn decode_bc4_channel(block: &[u8], signed: bool) -> [f32; 16] {
+    let (e0, e1, scale) = if signed {
+        // -128 is the same as -127
+        let endpoint = |byte: u8| (byte as i8).max(-127) as f32;
+        (endpoint(block[0]), endpoint(block[1]), 127.0)
+    } else {
+        (block[0] as f32, block[1] as f32, 255.0)
+    };
+
+    let mut values = [e0, e1, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    if e0 > e1 {
+        for i in 1..7 {
+            values[i + 1] = (e0 * (7 - i) as f32 + e1 * i as f32) / 7.0;
+        }
+    } else {
+        for i in 1..5 {
+            values[i + 1] = (e0 * (5 - i) as f32 + e1 * i as f32) / 5.0;
+        }
+        values[6] = if signed { -127.0 } else { 0.0 };
+        values[7] = scale;
+    }
+
+    let indices = block[2..8].iter().rev().fold(0u64, |bits, &byte| bits << 8 | byte as u64);
+    std::array::from_fn(|i| values[(indices >> (3 * i) & 7) as usize] / scale)
+}
+
+fn decode_bc4(block: &[u8], signed: bool) -> Block {
+    decode_bc4_channel(block, signed).map(|red| [red, 0.0, 0.0, 1.0])
+}
+
+fn decode_bc5(block: &[u8], signed: bool) -> Block {
+    let (red, green) = (decode_bc4_channel(block, signed), decode_bc4_channel(&block[8..], signed));
+    std::array::from_fn(|i| [red[i], green[i], 0.0, 1.0])
+}
+
+// BC6H and BC7 share partitions, anchors and interpolation weights
+
+// Bit i set if texel i is in the second subset
+const PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+const PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2], [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1], [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2], [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2], [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2], [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2], [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2], [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0], [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0], [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2], [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1], [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2], [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0], [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0], [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1], [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1], [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1], [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1], [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2], [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2], [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2], [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1], [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2], [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+// Texels whose index is stored with one bit less, the first texel of each subset after the first
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+const ANCHORS_3_SECOND: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+    3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+    8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+    3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+];
+
+const ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+    15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+    15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+    15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+// Subset of every texel and whether its index is stored with one bit less
+fn partition(subsets: usize, partition: usize) -> ([usize; 16], [bool; 16]) {
+    let mut anchors = [false; 16];
+    anchors[0] = true;
+
+    let texel_subsets = match subsets {
+        2 => {
+            anchors[ANCHORS_2[partition] as usize] = true;
+            std::array::from_fn(|i| (PARTITIONS_2[partition] >> i & 1) as usize)
+        }
+        3 => {
+            anchors[ANCHORS_3_SECOND[partition] as usize] = true;
+            anchors[ANCHORS_3_THIRD[partition] as usize] = true;
+            PARTITIONS_3[partition].map(|subset| subset as usize)
+        }
+        _ => [0; 16],
+    };
+
+    (texel_subsets, anchors)
+}
+
+fn read_indices(bits: &mut Bits, index_bits: u32, anchors: &[bool; 16]) -> [usize; 16] {
+    if index_bits == 0 {
+        return [0; 16];
+    }
+
+    std::array::from_fn(|i| bits.read(index_bits - anchors[i] as u32) as usize)
+}
+
+const WEIGHTS_2: [i32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [i32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [i32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn interpolate(e0: i32, e1: i32, index: usize, index_bits: u32) -> i32 {
+    let weight = match index_bits {
+        2 => WEIGHTS_2[index],
+        3 => WEIGHTS_3[index],
+        _ => WEIGHTS_4[index],
+    };
+
+    ((64 - weight) * e0 + weight * e1 + 32) >> 6
+}
+
+// BC7
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    // One per endpoint, or one per subset shared by both of its endpoints
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    // Separate indices for alpha, or for colors if the index selection bit is set
+    secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: true, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_p_bits: true, shared_p_bits: false, index_bits: 4, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+];
+
+fn decode_bc7(block: &[u8]) -> Block {
+    let mut bits = Bits::new(block);
+
+    // The mode is the number of zero bits before the first set one, none set is reserved
+    let mode = match (0..8).find(|_| bits.read(1) == 1) {
+        Some(mode) => &BC7_MODES[mode],
+        None => return [[0.0; 4]; 16],
+    };
+
+    let partition_index = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    let endpoints = mode.subsets * 2;
+    let mut colors = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for color in colors.iter_mut().take(endpoints) {
+            color[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for color in colors.iter_mut().take(endpoints) {
+        color[3] = bits.read(mode.alpha_bits);
+    }
+
+    let mut p_bits = [0; 6];
+    if mode.endpoint_p_bits {
+        for p_bit in p_bits.iter_mut().take(endpoints) {
+            *p_bit = bits.read(1);
+        }
+    } else if mode.shared_p_bits {
+        for subset in 0..mode.subsets {
+            let p_bit = bits.read(1);
+            p_bits[subset * 2] = p_bit;
+            p_bits[subset * 2 + 1] = p_bit;
+        }
+    }
+
+    // Endpoints to 8 bits, p-bits become their lowest bit and the top bits are repeated below
+    let has_p_bits = (mode.endpoint_p_bits || mode.shared_p_bits) as u32;
+    let expand = |value: u32, bits: u32, p_bit: u32| {
+        let (value, bits) = (value << has_p_bits | (p_bit * has_p_bits), bits + has_p_bits);
+        value << (8 - bits) | value >> (2 * bits - 8)
+    };
+
+    let mut endpoints_8 = [[0i32; 4]; 6];
+    for endpoint in 0..endpoints {
+        for channel in 0..3 {
+            endpoints_8[endpoint][channel] = expand(colors[endpoint][channel], mode.color_bits, p_bits[endpoint]) as i32;
+        }
+        endpoints_8[endpoint][3] = if mode.alpha_bits > 0 {
+            expand(colors[endpoint][3], mode.alpha_bits, p_bits[endpoint]) as i32
+        } else {
+            255
+        };
+    }
+
+    let (subsets, anchors) = partition(mode.subsets, partition_index);
+    let indices = read_indices(&mut bits, mode.index_bits, &anchors);
+    let mut first_anchor = [false; 16];
+    first_anchor[0] = true;
+    let secondary = read_indices(&mut bits, mode.secondary_index_bits, &first_anchor);
+
+    std::array::from_fn(|i| {
+        let (e0, e1) = (endpoints_8[subsets[i] * 2], endpoints_8[subsets[i] * 2 + 1]);
+        let ((color_index, color_bits), (alpha_index, alpha_bits)) = match (mode.secondary_index_bits, index_selection) {
+            (0, _) => ((indices[i], mode.index_bits), (indices[i], mode.index_bits)),
+            (_, 0) => ((indices[i], mode.index_bits), (secondary[i], mode.secondary_index_bits)),
+            _ => ((secondary[i], mode.secondary_index_bits), (indices[i], mode.index_bits)),
+        };
+
+        let mut texel = [
+            interpolate(e0[0], e1[0], color_index, color_bits) as u32,
+            interpolate(e0[1], e1[1], color_index, color_bits) as u32,
+            interpolate(e0[2], e1[2], color_index, color_bits) as u32,
+            interpolate(e0[3], e1[3], alpha_index, alpha_bits) as u32,
+        ];
+        if rotation > 0 {
+            texel.swap(rotation as usize - 1, 3);
+        }
+
+        rgba8(texel)
+    })
+}
+
+// BC6H
+
+// Fields of the endpoints: red, green and blue of w and x (first subset), then y and z
+const RW: usize = 0;
+const GW: usize = 1;
+const BW: usize = 2;
+const RX: usize = 3;
+const GX: usize = 4;
+const BX: usize = 5;
+const RY: usize = 6;
+const GY: usize = 7;
+const BY: usize = 8;
+const RZ: usize = 9;
+const GZ: usize = 10;
+const BZ: usize = 11;
+const D: usize = 12;
+
+struct Bc6hMode {
+    mode: u32,
+    subsets: usize,
+    // Other endpoints are stored as differences from w
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    // Field, then its bits in the order they're stored (backwards if the first is higher)
+    layout: &'static [(usize, u32, u32)],
+}
+
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode { mode: 0, subsets: 2, transformed: true, endpoint_bits: 10, delta_bits: [5, 5, 5], layout: &[
+        (GY, 4, 4), (BY, 4, 4), (BZ, 4, 4), (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4), (GZ, 4, 4),
+        (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4),
+        (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 1, subsets: 2, transformed: true, endpoint_bits: 7, delta_bits: [6, 6, 6], layout: &[
+        (GY, 5, 5), (GZ, 4, 4), (GZ, 5, 5), (RW, 0, 6), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 0, 6),
+        (BY, 5, 5), (BZ, 2, 2), (GY, 4, 4), (BW, 0, 6), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 5),
+        (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 2, subsets: 2, transformed: true, endpoint_bits: 11, delta_bits: [5, 4, 4], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4), (RW, 10, 10), (GY, 0, 3), (GX, 0, 3), (GW, 10, 10),
+        (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2),
+        (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 6, subsets: 2, transformed: true, endpoint_bits: 11, delta_bits: [4, 5, 4], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4),
+        (GW, 10, 10), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 3), (BZ, 0, 0),
+        (BZ, 2, 2), (RZ, 0, 3), (GY, 4, 4), (BZ, 3, 3), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 10, subsets: 2, transformed: true, endpoint_bits: 11, delta_bits: [4, 4, 5], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (BY, 4, 4), (GY, 0, 3), (GX, 0, 3),
+        (GW, 10, 10), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BW, 10, 10), (BY, 0, 3), (RY, 0, 3), (BZ, 1, 1),
+        (BZ, 2, 2), (RZ, 0, 3), (BZ, 4, 4), (BZ, 3, 3), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 14, subsets: 2, transformed: true, endpoint_bits: 9, delta_bits: [5, 5, 5], layout: &[
+        (RW, 0, 8), (BY, 4, 4), (GW, 0, 8), (GY, 4, 4), (BW, 0, 8), (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4),
+        (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4),
+        (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 18, subsets: 2, transformed: true, endpoint_bits: 8, delta_bits: [6, 5, 5], layout: &[
+        (RW, 0, 7), (GZ, 4, 4), (BY, 4, 4), (GW, 0, 7), (BZ, 2, 2), (GY, 4, 4), (BW, 0, 7), (BZ, 3, 3),
+        (BZ, 4, 4), (RX, 0, 5), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1),
+        (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 22, subsets: 2, transformed: true, endpoint_bits: 8, delta_bits: [5, 6, 5], layout: &[
+        (RW, 0, 7), (BZ, 0, 0), (BY, 4, 4), (GW, 0, 7), (GY, 5, 5), (GY, 4, 4), (BW, 0, 7), (GZ, 5, 5),
+        (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1),
+        (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 26, subsets: 2, transformed: true, endpoint_bits: 8, delta_bits: [5, 5, 6], layout: &[
+        (RW, 0, 7), (BZ, 1, 1), (BY, 4, 4), (GW, 0, 7), (BY, 5, 5), (GY, 4, 4), (BW, 0, 7), (BZ, 5, 5),
+        (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 5),
+        (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 30, subsets: 2, transformed: false, endpoint_bits: 6, delta_bits: [6, 6, 6], layout: &[
+        (RW, 0, 5), (GZ, 4, 4), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 0, 5), (GY, 5, 5), (BY, 5, 5),
+        (BZ, 2, 2), (GY, 4, 4), (BW, 0, 5), (GZ, 5, 5), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 5),
+        (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5), (D, 0, 4),
+    ] },
+    Bc6hMode { mode: 3, subsets: 1, transformed: false, endpoint_bits: 10, delta_bits: [10, 10, 10], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 9), (GX, 0, 9), (BX, 0, 9),
+    ] },
+    Bc6hMode { mode: 7, subsets: 1, transformed: true, endpoint_bits: 11, delta_bits: [9, 9, 9], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 8), (RW, 10, 10), (GX, 0, 8), (GW, 10, 10), (BX, 0, 8),
+        (BW, 10, 10),
+    ] },
+    Bc6hMode { mode: 11, subsets: 1, transformed: true, endpoint_bits: 12, delta_bits: [8, 8, 8], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 7), (RW, 11, 10), (GX, 0, 7), (GW, 11, 10), (BX, 0, 7),
+        (BW, 11, 10),
+    ] },
+    Bc6hMode { mode: 15, subsets: 1, transformed: true, endpoint_bits: 16, delta_bits: [4, 4, 4], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 15, 10), (GX, 0, 3), (GW, 15, 10), (BX, 0, 3),
+        (BW, 15, 10),
+    ] },
+];
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    value << shift >> shift
+}
+
+// Endpoints to 16 bits (17 with the sign)
+fn bc6h_unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xffff
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else {
+        let magnitude = value.abs();
+        let unquantized = if bits >= 16 || magnitude == 0 {
+            magnitude
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+
+        if value < 0 { -unquantized } else { unquantized }
+    }
+}
+
+// Interpolated values to half float bits
+fn bc6h_finish(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | ((-value * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (half >> 10 & 31) as i32;
+    let mantissa = (half & 1023) as f32;
+
+    match exponent {
+        0 => sign * mantissa * (2.0f32).powi(-24),
+        31 if mantissa == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * (2.0f32).powi(exponent - 15),
+    }
+}
+
+fn decode_bc6h(block: &[u8], signed: bool) -> Block {
+    let mut bits = Bits::new(block);
+
+    let mut mode = bits.read(2);
+    if mode > 1 {
+        mode |= bits.read(3) << 2;
+    }
+
+    // Reserved modes decode to black
+    let mode = match BC6H_MODES.iter().find(|candidate| candidate.mode == mode) {
+        Some(mode) => mode,
+        None => return [[0.0, 0.0, 0.0, 1.0]; 16],
+    };
+
+    let mut fields = [0i32; 13];
+    for &(field, first, last) in mode.layout {
+        if first <= last {
+            for bit in first..=last {
+                fields[field] |= (bits.read(1) as i32) << bit;
+            }
+        } else {
+            for bit in (last..=first).rev() {
+                fields[field] |= (bits.read(1) as i32) << bit;
+            }
+        }
+    }
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0i32; 3]; 4];
+    for (endpoint, components) in endpoints.iter_mut().enumerate().take(endpoint_count) {
+        components.copy_from_slice(&fields[endpoint * 3..endpoint * 3 + 3]);
+    }
+
+    let bits_mask = (1 << mode.endpoint_bits) - 1;
+    for channel in 0..3 {
+        if signed {
+            endpoints[0][channel] = sign_extend(endpoints[0][channel], mode.endpoint_bits);
+        }
+
+        let base = endpoints[0][channel];
+        for endpoint in endpoints.iter_mut().take(endpoint_count).skip(1) {
+            if mode.transformed {
+                let delta = sign_extend(endpoint[channel], mode.delta_bits[channel]);
+                endpoint[channel] = (base + delta) & bits_mask;
+                if signed {
+                    endpoint[channel] = sign_extend(endpoint[channel], mode.endpoint_bits);
+                }
+            } else if signed {
+                endpoint[channel] = sign_extend(endpoint[channel], mode.endpoint_bits);
+            }
+        }
+    }
+
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for component in endpoint.iter_mut() {
+            *component = bc6h_unquantize(*component, mode.endpoint_bits, signed);
+        }
+    }
+
+    let index_bits = if mode.subsets == 2 { 3 } else { 4 };
+    let (subsets, anchors) = partition(mode.subsets, fields[D] as usize);
+    let indices = read_indices(&mut bits, index_bits, &anchors);
+
+    std::array::from_fn(|i| {
+        let (e0, e1) = (endpoints[subsets[i] * 2], endpoints[subsets[i] * 2 + 1]);
+        let channel = |c: usize| half_to_f32(bc6h_finish(interpolate(e0[c], e1[c], indices[i], index_bits), signed));
+
+        [channel(0), channel(1), channel(2), 1.0]
+    })
+}
+
+// ETC2 and EAC, blocks are big endian with texels going column by column
+
+const ETC_MODIFIERS: [[i32; 4]; 8] = [
+    [2, 8, -2, -8],
+    [5, 17, -5, -17],
+    [9, 29, -9, -29],
+    [13, 42, -13, -42],
+    [18, 60, -18, -60],
+    [24, 80, -24, -80],
+    [33, 106, -33, -106],
+    [47, 183, -47, -183],
+];
+
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn read_u64_be(block: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&block[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+// `count` bits ending at bit `low`
+fn bits_at(block: u64, low: u32, count: u32) -> i32 {
+    (block >> low & ((1 << count) - 1)) as i32
+}
+
+fn extend_4(value: i32) -> i32 {
+    value << 4 | value
+}
+
+fn extend_5(value: i32) -> i32 {
+    value << 3 | value >> 2
+}
+
+// Index of the texel at x, y (as stored in `Block`) in ETC's column by column order
+fn etc_texel(i: usize) -> usize {
+    (i % 4) * 4 + i / 4
+}
+
+fn decode_etc2(block: &[u8], punch_through: bool) -> Block {
+    let block = read_u64_be(block);
+    // Without punch-through alpha this picks individual or differential mode,
+    // with it this bit says whether the block is opaque
+    let bit_33 = block >> 33 & 1 == 1;
+    let (differential, opaque) = if punch_through { (true, bit_33) } else { (bit_33, true) };
+    let flip = block >> 32 & 1 == 1;
+
+    let texel_index = |i: usize| {
+        let k = etc_texel(i);
+        (block >> (k + 16) & 1) << 1 | block >> k & 1
+    };
+
+    let clamp = |value: i32| value.clamp(0, 255) as u32;
+    let paint = |colors: [[i32; 3]; 4]| -> Block {
+        std::array::from_fn(|i| {
+            let index = texel_index(i) as usize;
+            if !opaque && index == 2 {
+                return [0.0; 4];
+            }
+
+            let [r, g, b] = colors[index];
+            rgba8([clamp(r), clamp(g), clamp(b), 255])
+        })
+    };
+
+    let (base_0, base_1) = if differential {
+        let r = bits_at(block, 59, 5);
+        let g = bits_at(block, 51, 5);
+        let b = bits_at(block, 43, 5);
+        let (dr, dg, db) = (sign_extend(bits_at(block, 56, 3), 3), sign_extend(bits_at(block, 48, 3), 3), sign_extend(bits_at(block, 40, 3), 3));
+
+        if !(0..32).contains(&(r + dr)) {
+            // T mode
+            let c0 = [bits_at(block, 59, 2) << 2 | bits_at(block, 56, 2), bits_at(block, 52, 4), bits_at(block, 48, 4)].map(extend_4);
+            let c1 = [bits_at(block, 44, 4), bits_at(block, 40, 4), bits_at(block, 36, 4)].map(extend_4);
+            let d = ETC_DISTANCES[(bits_at(block, 34, 2) << 1 | bits_at(block, 32, 1)) as usize];
+
+            return paint([c0, c1.map(|c| c + d), c1, c1.map(|c| c - d)]);
+        }
+
+        if !(0..32).contains(&(g + dg)) {
+            // H mode
+            let c0 = [
+                bits_at(block, 59, 4),
+                bits_at(block, 56, 3) << 1 | bits_at(block, 52, 1),
+                bits_at(block, 51, 1) << 3 | bits_at(block, 47, 3),
+            ];
+            let c1 = [bits_at(block, 43, 4), bits_at(block, 39, 4), bits_at(block, 35, 4)];
+            let order = ((c0[0] << 8 | c0[1] << 4 | c0[2]) >= (c1[0] << 8 | c1[1] << 4 | c1[2])) as i32;
+            let d = ETC_DISTANCES[(bits_at(block, 34, 1) << 2 | bits_at(block, 32, 1) << 1 | order) as usize];
+            let (c0, c1) = (c0.map(extend_4), c1.map(extend_4));
+
+            return paint([c0.map(|c| c + d), c0.map(|c| c - d), c1.map(|c| c + d), c1.map(|c| c - d)]);
+        }
+
+        if !(0..32).contains(&(b + db)) {
+            return decode_etc2_planar(block);
+        }
+
+        ([r, g, b].map(extend_5), [r + dr, g + dg, b + db].map(extend_5))
+    } else {
+        (
+            [bits_at(block, 60, 4), bits_at(block, 52, 4), bits_at(block, 44, 4)].map(extend_4),
+            [bits_at(block, 56, 4), bits_at(block, 48, 4), bits_at(block, 40, 4)].map(extend_4),
+        )
+    };
+
+    let tables = [bits_at(block, 37, 3) as usize, bits_at(block, 34, 3) as usize];
+
+    std::array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let subblock = if flip { y / 2 } else { x / 2 };
+        let index = texel_index(i) as usize;
+
+        let modifier = match index {
+            2 if !opaque => return [0.0; 4],
+            0 if !opaque => 0,
+            _ => ETC_MODIFIERS[tables[subblock]][index],
+        };
+
+        let base = if subblock == 0 { base_0 } else { base_1 };
+        rgba8([clamp(base[0] + modifier), clamp(base[1] + modifier), clamp(base[2] + modifier), 255])
+    })
+}
+
+fn decode_etc2_planar(block: u64) -> Block {
+    let extend_6 = |value: i32| value << 2 | value >> 4;
+    let extend_7 = |value: i32| value << 1 | value >> 6;
+
+    let origin = [
+        extend_6(bits_at(block, 57, 6)),
+        extend_7(bits_at(block, 56, 1) << 6 | bits_at(block, 49, 6)),
+        extend_6(bits_at(block, 48, 1) << 5 | bits_at(block, 43, 2) << 3 | bits_at(block, 39, 3)),
+    ];
+    let horizontal = [
+        extend_6(bits_at(block, 34, 5) << 1 | bits_at(block, 32, 1)),
+        extend_7(bits_at(block, 25, 7)),
+        extend_6(bits_at(block, 19, 6)),
+    ];
+    let vertical = [
+        extend_6(bits_at(block, 13, 6)),
+        extend_7(bits_at(block, 6, 7)),
+        extend_6(bits_at(block, 0, 6)),
+    ];
+
+    std::array::from_fn(|i| {
+        let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+        let channel = |c: usize| ((x * (horizontal[c] - origin[c]) + y * (vertical[c] - origin[c]) + 4 * origin[c] + 2) >> 2).clamp(0, 255) as u32;
+
+        rgba8([channel(0), channel(1), channel(2), 255])
+    })
+}
+
+// 8 bit alpha channel of ETC2 RGBA
+fn decode_eac_alpha(block: &[u8]) -> [f32; 16] {
+    let block = read_u64_be(block);
+    let base = bits_at(block, 56, 8);
+    let multiplier = bits_at(block, 52, 4);
+    let modifiers = EAC_MODIFIERS[bits_at(block, 48, 4) as usize];
+
+    std::array::from_fn(|i| {
+        let index = bits_at(block, 45 - 3 * etc_texel(i) as u32, 3) as usize;
+        (base + modifiers[index] * multiplier).clamp(0, 255) as f32 / 255.0
+    })
+}
+
+fn decode_etc2_eac(block: &[u8]) -> Block {
+    let mut texels = decode_etc2(&block[8..], false);
+    for (texel, alpha) in texels.iter_mut().zip(decode_eac_alpha(block)) {
+        texel[3] = alpha;
+    }
+
+    texels
+}
+
+// 11 bit channel, [0, 1] or [-1, 1] if signed
+fn decode_eac_channel(block: &[u8], signed: bool) -> [f32; 16] {
+    let block = read_u64_be(block);
+    let multiplier = bits_at(block, 52, 4);
+    let modifiers = EAC_MODIFIERS[bits_at(block, 48, 4) as usize];
+    let scale = if multiplier == 0 { 1 } else { multiplier * 8 };
+
+    std::array::from_fn(|i| {
+        let modifier = modifiers[bits_at(block, 45 - 3 * etc_texel(i) as u32, 3) as usize] * scale;
+
+        if signed {
+            let base = (bits_at(block, 56, 8) as u8 as i8).max(-127) as i32;
+            (base * 8 + modifier).clamp(-1023, 1023) as f32 / 1023.0
+        } else {
+            (bits_at(block, 56, 8) * 8 + 4 + modifier).clamp(0, 2047) as f32 / 2047.0
+        }
+    })
+}
+
+fn decode_eac_r11(block: &[u8], signed: bool) -> Block {
+    decode_eac_channel(block, signed).map(|red| [red, 0.0, 0.0, 1.0])
+}
+
+fn decode_eac_rg11(block: &[u8], signed: bool) -> Block {
+    let (red, green) = (decode_eac_channel(block, signed), decode_eac_channel(&block[8..], signed));
+    std::array::from_fn(|i| [red[i], green[i], 0.0, 1.0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fields as (value, bit count), from the lowest bit of the first byte like `Bits` reads them
+    fn pack(fields: &[(u32, u32)]) -> [u8; 16] {
+        let (mut value, mut position) = (0u128, 0);
+        for &(field, count) in fields {
+            value |= (field as u128) << position;
+            position += count;
+        }
+
+        assert_eq!(position, 128);
+        value.to_le_bytes()
+    }
+
+    fn fields(values: &[u32], bits: u32) -> Vec<(u32, u32)> {
+        values.iter().map(|&value| (value, bits)).collect()
+    }
+
+    fn indices(values: [u32; 16], bits: u32, anchors: &[usize]) -> Vec<(u32, u32)> {
+        values.iter().enumerate().map(|(i, &value)| (value, bits - anchors.contains(&i) as u32)).collect()
+    }
+
+    fn bytes(block: Block) -> [[u8; 4]; 16] {
+        block.map(|texel| texel.map(|channel| (channel * 255.0).round() as u8))
+    }
+
+    // (value, lowest bit, bit count) of a big endian ETC block
+    fn etc_block(fields: &[(u64, u32, u32)]) -> [u8; 8] {
+        fields.iter().fold(0u64, |block, &(value, low, count)| {
+            assert!(value < 1 << count);
+            block | value << low
+        }).to_be_bytes()
+    }
+
+    // 2 bit indices of texels row by row, stored column by column
+    fn etc_indices(values: [u64; 16]) -> Vec<(u64, u32, u32)> {
+        values.iter().enumerate().flat_map(|(i, &value)| {
+            let k = etc_texel(i) as u32;
+            [(value >> 1, k + 16, 1), (value & 1, k, 1)]
+        }).collect()
+    }
+
+    // 3 bit indices of texels row by row, stored column by column from the top bits
+    fn eac_block(base: u64, multiplier: u64, table: u64, values: [u64; 16]) -> [u8; 8] {
+        let mut block = vec![(base, 56, 8), (multiplier, 52, 4), (table, 48, 4)];
+        block.extend(values.iter().enumerate().map(|(i, &value)| (value, 45 - 3 * etc_texel(i) as u32, 3)));
+        etc_block(&block)
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        let mut block = [0; 8];
+        block[..2].copy_from_slice(&0xf800u16.to_le_bytes());
+        block[2..4].copy_from_slice(&0x001fu16.to_le_bytes());
+        block[4] = 0b11_10_01_00;
+
+        let texels = bytes(decode_bc1(&block, true));
+        assert_eq!(texels[..4], [[255, 0, 0, 255], [0, 0, 255, 255], [170, 0, 85, 255], [85, 0, 170, 255]]);
+        assert_eq!(texels[4], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc1_three_colors() {
+        let mut block = [0; 8];
+        block[..2].copy_from_slice(&0x001fu16.to_le_bytes());
+        block[2..4].copy_from_slice(&0xf800u16.to_le_bytes());
+        block[4] = 0b11_10_01_00;
+
+        let texels = bytes(decode_bc1(&block, true));
+        assert_eq!(texels[..4], [[0, 0, 255, 255], [255, 0, 0, 255], [128, 0, 128, 255], [0, 0, 0, 0]]);
+        assert_eq!(bytes(decode_bc1(&block, false))[3], [0, 0, 0, 255]);
+        // BC2 and BC3 colors never use 3 color mode
+        assert_eq!(bytes(decode_bc1_colors(&block, false, false))[2], [85, 0, 170, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_0() {
+        let mut index_values = [7; 16];
+        (index_values[0], index_values[3], index_values[15]) = (0, 3, 3);
+        let block = pack(&[
+            vec![(1, 1), (0, 4)],
+            fields(&[0, 15, 0, 0, 0, 0], 4),
+            fields(&[0, 0, 0, 15, 0, 0], 4),
+            fields(&[0, 0, 0, 0, 0, 15], 4),
+            fields(&[0, 0, 0, 0, 0, 1], 1),
+            indices(index_values, 3, &[0, 3, 15]),
+        ].concat());
+
+        let texels = bytes(decode_bc7(&block));
+        assert_eq!(texels[0], [0, 0, 0, 255]);
+        assert_eq!(texels[1], [247, 0, 0, 255]);
+        assert_eq!(texels[2], [0, 247, 0, 255]);
+        assert_eq!(texels[3], [0, 104, 0, 255]);
+        assert_eq!(texels[9], [8, 8, 255, 255]);
+        assert_eq!(texels[15], [3, 3, 108, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_1() {
+        let mut index_values = [4; 16];
+        (index_values[0], index_values[1], index_values[2], index_values[15]) = (0, 7, 7, 3);
+        let block = pack(&[
+            vec![(0b10, 2), (0, 6)],
+            fields(&[0, 63, 0, 0], 6),
+            fields(&[0, 0, 0, 32], 6),
+            fields(&[0, 0, 0, 63], 6),
+            fields(&[0, 1], 1),
+            indices(index_values, 3, &[0, 15]),
+        ].concat());
+
+        let texels = bytes(decode_bc7(&block));
+        assert_eq!(texels[0], [0, 0, 0, 255]);
+        assert_eq!(texels[1], [253, 0, 0, 255]);
+        assert_eq!(texels[2], [2, 131, 255, 255]);
+        assert_eq!(texels[15], [2, 56, 109, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_2() {
+        let mut index_values = [0; 16];
+        (index_values[1], index_values[3], index_values[9], index_values[15]) = (2, 1, 3, 1);
+        let block = pack(&[
+            vec![(0b100, 3), (0, 6)],
+            fields(&[31, 0, 0, 16, 1, 31], 5),
+            fields(&[31, 0, 0, 16, 2, 0], 5),
+            fields(&[31, 0, 0, 16, 3, 31], 5),
+            indices(index_values, 2, &[0, 3, 15]),
+        ].concat());
+
+        let texels = bytes(decode_bc7(&block));
+        assert_eq!(texels[0], [255, 255, 255, 255]);
+        assert_eq!(texels[1], [84, 84, 84, 255]);
+        assert_eq!(texels[3], [43, 43, 43, 255]);
+        assert_eq!(texels[9], [255, 0, 255, 255]);
+        assert_eq!(texels[15], [89, 11, 100, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_3() {
+        let mut index_values = [0; 16];
+        (index_values[1], index_values[2], index_values[15]) = (3, 2, 1);
+        let block = pack(&[
+            vec![(0b1000, 4), (0, 6)],
+            fields(&[10, 127, 0, 127], 7),
+            fields(&[20, 0, 0, 127], 7),
+            fields(&[30, 64, 0, 127], 7),
+            fields(&[1, 0, 0, 1], 1),
+            indices(index_values, 2, &[0, 15]),
+        ].concat());
+
+        let texels = bytes(decode_bc7(&block));
+        assert_eq!(texels[0], [21, 41, 61, 255]);
+        assert_eq!(texels[1], [254, 0, 128, 255]);
+        assert_eq!(texels[2], [171, 171, 171, 255]);
+        assert_eq!(texels[15], [84, 84, 84, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_4_rotation_and_index_selection() {
+        let mut alpha_indices = [0; 16];
+        (alpha_indices[1], alpha_indices[2]) = (3, 1);
+        let mut color_indices = [0; 16];
+        (color_indices[0], color_indices[1]) = (3, 7);
+        // Rotation 1 swaps red and alpha, the index selection bit gives colors the 3 bit indices
+        let block = pack(&[
+            vec![(0b10000, 5), (1, 2), (1, 1)],
+            fields(&[0, 31], 5),
+            fields(&[31, 0], 5),
+            fields(&[8, 8], 5),
+            fields(&[0, 63], 6),
+            indices(alpha_indices, 2, &[0]),
+            indices(color_indices, 3, &[0]),
+        ].concat());
+
+        let texels = bytes(decode_bc7(&block));
+        assert_eq!(texels[0], [0, 147, 66, 108]);
+        assert_eq!(texels[1], [255, 0, 66, 255]);
+        assert_eq!(texels[2], [84, 255, 66, 0]);
+    }
+
+    #[test]
+    fn bc7_mode_5_rotation() {
+        let mut color_indices = [0; 16];
+        (color_indices[1], color_indices[5]) = (2, 3);
+        let mut alpha_indices = [0; 16];
+        (alpha_indices[0], alpha_indices[1]) = (1, 3);
+        // Rotation 3 swaps blue and alpha
+        let block = pack(&[
+            vec![(0b100000, 6), (3, 2)],
+            fields(&[127, 0], 7),
+            fields(&[64, 64], 7),
+            fields(&[0, 127], 7),
+            fields(&[200, 10], 8),
+            indices(color_indices, 2, &[0]),
+            indices(alpha_indices, 2, &[0]),
+        ].concat());
+
+        let texels = bytes(decode_bc7(&block));
+        assert_eq!(texels[0], [255, 129, 138, 0]);
+        assert_eq!(texels[1], [84, 129, 10, 171]);
+        assert_eq!(texels[5], [0, 129, 200, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_6() {
+        let mut index_values = [0; 16];
+        (index_values[1], index_values[2]) = (15, 8);
+        let block = pack(&[
+            vec![(0b1000000, 7)],
+            fields(&[100, 0], 7),
+            fields(&[0, 127], 7),
+            fields(&[50, 25], 7),
+            fields(&[127, 0], 7),
+            fields(&[1, 0], 1),
+            indices(index_values, 4, &[0]),
+        ].concat());
+
+        let texels = bytes(decode_bc7(&block));
+        assert_eq!(texels[0], [201, 1, 101, 255]);
+        assert_eq!(texels[1], [0, 254, 50, 0]);
+        assert_eq!(texels[2], [94, 135, 74, 120]);
+    }
+
+    #[test]
+    fn bc7_mode_7() {
+        let mut index_values = [0; 16];
+        (index_values[1], index_values[15]) = (3, 1);
+        // Partition 13 puts the bottom two rows in the second subset
+        let block = pack(&[
+            vec![(0b10000000, 8), (13, 6)],
+            fields(&[31, 0, 0, 0], 5),
+            fields(&[0, 0, 31, 0], 5),
+            fields(&[0, 0, 0, 31], 5),
+            fields(&[31, 0, 16, 31], 5),
+            fields(&[1, 0, 0, 1], 1),
+            indices(index_values, 2, &[0, 15]),
+        ].concat());
+
+        let texels = bytes(decode_bc7(&block));
+        assert_eq!(texels[0], [255, 4, 4, 255]);
+        assert_eq!(texels[1], [0, 0, 0, 0]);
+        assert_eq!(texels[8], [0, 251, 0, 130]);
+        assert_eq!(texels[15], [1, 170, 84, 171]);
+    }
+
+    fn halves(bits: [u16; 3]) -> [f32; 4] {
+        [half_to_f32(bits[0]), half_to_f32(bits[1]), half_to_f32(bits[2]), 1.0]
+    }
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x0001), (2.0f32).powi(-24));
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+    }
+
+    // Mode 0b00111: one subset, 11 bit endpoint w and 9 bit deltas to x
+    fn bc6h_block(w: [u32; 3], deltas: [u32; 3]) -> [u8; 16] {
+        let mut index_values = [0; 16];
+        (index_values[1], index_values[2]) = (15, 8);
+
+        pack(&[
+            vec![(0b00111, 5), (w[0] & 1023, 10), (w[1] & 1023, 10), (w[2] & 1023, 10)],
+            vec![(deltas[0], 9), (w[0] >> 10, 1), (deltas[1], 9), (w[1] >> 10, 1), (deltas[2], 9), (w[2] >> 10, 1)],
+            indices(index_values, 4, &[0]),
+        ].concat())
+    }
+
+    #[test]
+    fn bc6h_unsigned_transformed() {
+        // x = (150, 1000, 2047)
+        let texels = decode_bc6h(&bc6h_block([100, 1024, 2000], [50, 0x1ff & -24i32 as u32, 47]), false);
+
+        assert_eq!(texels[0], halves([1557, 15879, 31007]));
+        assert_eq!(texels[1], halves([2332, 15507, 31743]));
+        assert_eq!(texels[2], halves([1969, 15682, 31398]));
+    }
+
+    #[test]
+    fn bc6h_signed_transformed() {
+        // w = (-100, 200, 0), x = (-150, 300, -1)
+        let texels = decode_bc6h(&bc6h_block([0x7ff & -100i32 as u32, 200, 0], [0x1ff & -50i32 as u32, 100, 0x1ff]), true);
+
+        assert_eq!(texels[0], halves([35883, 6215, 0]));
+        assert_eq!(texels[1], halves([37433, 9315, 32814]));
+        assert_eq!(texels[2], halves([36706, 7862, 32792]));
+    }
+
+    #[test]
+    fn etc2_individual() {
+        let mut index_values = [0; 16];
+        (index_values[1], index_values[2], index_values[15]) = (1, 2, 3);
+        let block = etc_block(&[
+            vec![(15, 60, 4), (0, 56, 4), (0, 52, 4), (15, 48, 4), (8, 44, 4), (0, 40, 4), (0, 37, 3), (7, 34, 3)],
+            etc_indices(index_values),
+        ].concat());
+
+        let texels = bytes(decode_etc2(&block, false));
+        assert_eq!(texels[0], [255, 2, 138, 255]);
+        assert_eq!(texels[1], [255, 8, 144, 255]);
+        assert_eq!(texels[2], [0, 208, 0, 255]);
+        assert_eq!(texels[15], [0, 72, 0, 255]);
+    }
+
+    #[test]
+    fn etc2_differential() {
+        let mut index_values = [0; 16];
+        (index_values[7], index_values[12]) = (1, 3);
+        // Flipped, so the subblocks are the top and bottom halves
+        let block = etc_block(&[
+            vec![(16, 59, 5), (3, 56, 3), (31, 51, 5), (0b100, 48, 3), (0, 43, 5), (0, 40, 3)],
+            vec![(1, 37, 3), (3, 34, 3), (1, 33, 1), (1, 32, 1)],
+            etc_indices(index_values),
+        ].concat());
+
+        let texels = bytes(decode_etc2(&block, false));
+        assert_eq!(texels[0], [137, 255, 5, 255]);
+        assert_eq!(texels[7], [149, 255, 17, 255]);
+        assert_eq!(texels[12], [114, 180, 0, 255]);
+    }
+
+    #[test]
+    fn etc2_t_mode() {
+        // Red plus its difference overflows
+        let block = etc_block(&[
+            vec![(0b111, 61, 3), (0b10, 59, 2), (0b10, 56, 2), (5, 52, 4), (0, 48, 4)],
+            vec![(15, 44, 4), (0, 40, 4), (8, 36, 4), (0b11, 34, 2), (1, 33, 1), (1, 32, 1)],
+            etc_indices([0, 1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        ].concat());
+
+        let texels = bytes(decode_etc2(&block, false));
+        assert_eq!(texels[..4], [[170, 85, 0, 255], [255, 64, 200, 255], [255, 0, 136, 255], [191, 0, 72, 255]]);
+    }
+
+    #[test]
+    fn etc2_h_mode() {
+        // Green plus its difference overflows
+        let block = etc_block(&[
+            vec![(8, 59, 4), (0b011, 56, 3), (0b111, 53, 3), (1, 52, 1), (1, 51, 1), (0b100, 47, 3)],
+            vec![(2, 43, 4), (15, 39, 4), (0, 35, 4), (1, 34, 1), (1, 33, 1)],
+            etc_indices([0, 1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        ].concat());
+
+        let texels = bytes(decode_etc2(&block, false));
+        assert_eq!(texels[..4], [[168, 151, 236, 255], [104, 87, 172, 255], [66, 255, 32, 255], [2, 223, 0, 255]]);
+    }
+
+    #[test]
+    fn etc2_planar() {
+        // Blue plus its difference overflows
+        let block = etc_block(&[
+            vec![(32, 57, 6), (32, 49, 6), (0b111, 45, 3), (0b11, 43, 2), (0b100, 39, 3)],
+            vec![(31, 34, 5), (1, 33, 1), (1, 32, 1), (32, 19, 6), (127, 6, 7)],
+        ].concat());
+
+        let texels = bytes(decode_etc2(&block, false));
+        assert_eq!(texels[0], [130, 64, 113, 255]);
+        assert_eq!(texels[3], [224, 16, 126, 255]);
+        assert_eq!(texels[9], [96, 144, 61, 255]);
+        assert_eq!(texels[12], [33, 207, 28, 255]);
+        assert_eq!(texels[15], [126, 159, 41, 255]);
+    }
+
+    #[test]
+    fn etc2_punch_through() {
+        let mut index_values = [0; 16];
+        (index_values[1], index_values[2]) = (2, 1);
+        // Differential bit cleared: not opaque
+        let block = etc_block(&[
+            vec![(16, 59, 5), (16, 51, 5), (16, 43, 5), (0, 37, 3), (0, 34, 3)],
+            etc_indices(index_values),
+        ].concat());
+
+        let texels = bytes(decode_etc2(&block, true));
+        assert_eq!(texels[0], [132, 132, 132, 255]);
+        assert_eq!(texels[1], [0, 0, 0, 0]);
+        assert_eq!(texels[2], [140, 140, 140, 255]);
+    }
+
+    #[test]
+    fn eac_r11_unsigned() {
+        let mut index_values = [0; 16];
+        (index_values[4], index_values[1]) = (7, 3);
+        let texels = decode_eac_channel(&eac_block(128, 2, 0, index_values), false);
+        assert_eq!(texels[0], 980.0 / 2047.0);
+        assert_eq!(texels[4], 1252.0 / 2047.0);
+        assert_eq!(texels[1], 788.0 / 2047.0);
+
+        assert_eq!(decode_eac_channel(&eac_block(255, 15, 0, [7; 16]), false)[0], 1.0);
+        // A multiplier of 0 adds the modifier unscaled
+        assert_eq!(decode_eac_channel(&eac_block(10, 0, 0, [7; 16]), false)[0], 98.0 / 2047.0);
+    }
+
+    #[test]
+    fn eac_r11_signed() {
+        let mut index_values = [0; 16];
+        (index_values[4], index_values[1]) = (7, 4);
+        let texels = decode_eac_channel(&eac_block(0x9c, 3, 13, index_values), true);
+        assert_eq!(texels[0], -824.0 / 1023.0);
+        assert_eq!(texels[4], -584.0 / 1023.0);
+        assert_eq!(texels[1], -800.0 / 1023.0);
+
+        // -128 is the same as -127
+        assert_eq!(decode_eac_channel(&eac_block(0x80, 1, 13, [4; 16]), true)[0], -1016.0 / 1023.0);
+    }
+
+    #[test]
+    fn sizes_that_overflow() {
+        assert_eq!(TextureFormat::Bc1.data_size(5, 3), Ok(16));
+        assert_eq!(TextureFormat::Bc7.data_size(8, 8), Ok(64));
+        assert!(TextureFormat::Bc7.data_size(0xffffffff, 0xffffffff).is_err());
+        assert!(TextureFormat::Rgba8.data_size(usize::MAX, 2).is_err());
+        assert!(TextureFormat::Bc7.decode(0xffffffff, 0xffffffff, &[0; 16]).is_err());
+    }
+}
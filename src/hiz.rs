@@ -0,0 +1,144 @@
+pub const HIZ_TILE_SIZE: usize = 8;
+
+pub struct DepthLevel {
+    pub width: usize,
+    pub height: usize,
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+impl DepthLevel {
+    fn new(width: usize, height: usize) -> DepthLevel {
+        DepthLevel {
+            width, height,
+            min: vec![f32::NEG_INFINITY; width * height],
+            max: vec![f32::NEG_INFINITY; width * height],
+        }
+    }
+}
+
+// Min/max depth of every HIZ_TILE_SIZE^2 tile of a buffer, halved level after level down
+// to a single entry. Depth only ever grows between clears, so an outdated pyramid
+// still gives conservative answers: it can only report too much as visible.
+pub struct DepthPyramid {
+    pub levels: Vec<DepthLevel>,
+    dirty: Vec<bool>,
+}
+
+impl DepthPyramid {
+    pub fn new(width: usize, height: usize) -> DepthPyramid {
+        let mut levels = vec![DepthLevel::new(
+            width.div_ceil(HIZ_TILE_SIZE),
+            height.div_ceil(HIZ_TILE_SIZE)
+        )];
+
+        loop {
+            let last = &levels[levels.len() - 1];
+            if last.width <= 1 && last.height <= 1 {
+                break;
+            }
+            levels.push(DepthLevel::new(last.width.div_ceil(2), last.height.div_ceil(2)));
+        }
+
+        DepthPyramid {
+            dirty: vec![false; levels[0].width * levels[0].height],
+            levels,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for level in self.levels.iter_mut() {
+            level.min.fill(f32::NEG_INFINITY);
+            level.max.fill(f32::NEG_INFINITY);
+        }
+        self.dirty.fill(false);
+    }
+
+    pub fn mark_dirty(&mut self, x: usize, y: usize) {
+        self.dirty[(y / HIZ_TILE_SIZE) * self.levels[0].width + x / HIZ_TILE_SIZE] = true;
+    }
+
+    // Recomputes the tiles written to since the last update and everything above them
+    pub fn update(&mut self, width: usize, height: usize, depth_at: impl Fn(usize, usize) -> f32) {
+        if !self.dirty.contains(&true) {
+            return;
+        }
+
+        let tiles = &mut self.levels[0];
+        for (i, dirty) in self.dirty.iter_mut().enumerate() {
+            if !*dirty {
+                continue;
+            }
+            *dirty = false;
+
+            let (tile_x, tile_y) = (i % tiles.width, i / tiles.width);
+            let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+            for y in tile_y * HIZ_TILE_SIZE..((tile_y + 1) * HIZ_TILE_SIZE).min(height) {
+                for x in tile_x * HIZ_TILE_SIZE..((tile_x + 1) * HIZ_TILE_SIZE).min(width) {
+                    let depth = depth_at(x, y);
+                    min = min.min(depth);
+                    max = max.max(depth);
+                }
+            }
+
+            tiles.min[i] = min;
+            tiles.max[i] = max;
+        }
+
+        for level in 1..self.levels.len() {
+            let (lower, upper) = self.levels.split_at_mut(level);
+            let (lower, upper) = (&lower[level - 1], &mut upper[0]);
+
+            for y in 0..upper.height {
+                for x in 0..upper.width {
+                    let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+                    for child_y in y * 2..(y * 2 + 2).min(lower.height) {
+                        for child_x in x * 2..(x * 2 + 2).min(lower.width) {
+                            min = min.min(lower.min[child_y * lower.width + child_x]);
+                            max = max.max(lower.max[child_y * lower.width + child_x]);
+                        }
+                    }
+
+                    upper.min[y * upper.width + x] = min;
+                    upper.max[y * upper.width + x] = max;
+                }
+            }
+        }
+    }
+
+    pub fn tile_visible(&self, tile_x: usize, tile_y: usize, depth: f32) -> bool {
+        self.levels[0].min[tile_y * self.levels[0].width + tile_x] < depth
+    }
+
+    // Whether anything with a depth up to `depth` could still pass the depth test
+    // somewhere in the pixel rectangle [x0, x1) x [y0, y1)
+    pub fn rect_visible(&self, x0: usize, y0: usize, x1: usize, y1: usize, depth: f32) -> bool {
+        if x0 >= x1 || y0 >= y1 {
+            return false;
+        }
+
+        let (mut tx0, mut ty0) = (x0 / HIZ_TILE_SIZE, y0 / HIZ_TILE_SIZE);
+        let (mut tx1, mut ty1) = ((x1 - 1) / HIZ_TILE_SIZE, (y1 - 1) / HIZ_TILE_SIZE);
+
+        // Climb until the rectangle touches at most 2x2 entries
+        let mut level = 0;
+        while (tx1 - tx0 > 1 || ty1 - ty0 > 1) && level + 1 < self.levels.len() {
+            tx0 /= 2;
+            ty0 /= 2;
+            tx1 /= 2;
+            ty1 /= 2;
+            level += 1;
+        }
+
+        let level = &self.levels[level];
+        for y in ty0..=ty1.min(level.height - 1) {
+            for x in tx0..=tx1.min(level.width - 1) {
+                if level.min[y * level.width + x] < depth {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
@@ -0,0 +1,281 @@
+use crate::utils::*;
+use crate::cubemap::*;
+use crate::texture::*;
+
+// Image based lighting, all precomputed on the CPU: environments come in as equirectangular
+// images, diffuse lighting is stored as spherical harmonics and specular as a cube map whose
+// mip levels are prefiltered for increasing roughness, along with a BRDF lookup table
+// (split sum approximation).
+
+// Longitude along u starting behind -Z, latitude along v from +Y at the top
+pub fn equirectangular_to_cube_map(texture: &Texture, size: usize) -> Result<CubeMap, &'static str> {
+    let sampler = Sampler {
+        wrap_v: Wrap::Clamp,
+        ..Sampler::new(Filter::Bilinear, Wrap::Repeat)
+    };
+
+    cube_map_from_fn(size, |direction| {
+        let direction = direction.normalize();
+        let uv = Vec2::new(
+            0.5 + direction.x.atan2(-direction.z) / (2.0 * consts::PI),
+            direction.y.clamp(-1.0, 1.0).acos() / consts::PI
+        );
+
+        sampler.sample(texture, uv)
+    })
+}
+
+// Float cube map with every texel computed from the direction through its center
+pub fn cube_map_from_fn(size: usize, texel: impl Fn(Vec3) -> Vec4) -> Result<CubeMap, &'static str> {
+    let face = |face: CubeFace| Texture::from_rgba32f(size, size, face_directions(face, size)
+        .flat_map(|direction| texel(direction).to_array().map(to_f32))
+        .collect());
+
+    CubeMap::from_faces([
+        face(CubeFace::PositiveX)?,
+        face(CubeFace::NegativeX)?,
+        face(CubeFace::PositiveY)?,
+        face(CubeFace::NegativeY)?,
+        face(CubeFace::PositiveZ)?,
+        face(CubeFace::NegativeZ)?,
+    ])
+}
+
+fn face_directions(face: CubeFace, size: usize) -> impl Iterator<Item = Vec3> {
+    (0..size * size).map(move |i| {
+        let uv = (Vec2::new((i % size) as real, (i / size) as real) + Vec2::splat(0.5)) / size as real;
+        face.direction(uv)
+    })
+}
+
+// Third order (9 coefficients) spherical harmonics of an environment's radiance
+#[derive(Clone, Copy, Debug)]
+pub struct SphericalHarmonics {
+    pub coefficients: [Vec3; 9],
+}
+
+fn sh_basis(d: Vec3) -> [real; 9] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+impl SphericalHarmonics {
+    pub fn from_cube_map(cube_map: &CubeMap) -> SphericalHarmonics {
+        let mut coefficients = [Vec3::ZERO; 9];
+        let mut total_weight = 0.0;
+
+        for (face, texture) in CUBE_FACES.iter().zip(cube_map.faces.iter()) {
+            let size = texture.width;
+
+            for (i, direction) in face_directions(*face, size).enumerate() {
+                // Texels near the face's corners cover a smaller solid angle
+                let weight = 1.0 / direction.length_squared().powf(1.5);
+                let radiance = texture.texel(i % size, i / size).truncate();
+                let basis = sh_basis(direction.normalize());
+
+                for (coefficient, basis) in coefficients.iter_mut().zip(basis) {
+                    *coefficient += radiance * basis * weight;
+                }
+                total_weight += weight;
+            }
+        }
+
+        let normalization = 4.0 * consts::PI / total_weight;
+        for coefficient in coefficients.iter_mut() {
+            *coefficient *= normalization;
+        }
+
+        SphericalHarmonics { coefficients }
+    }
+
+    // Light a white Lambertian surface facing `normal` reflects (irradiance over pi)
+    pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+        // Cosine lobe convolution per band, over pi
+        const BANDS: [real; 9] = [1.0, 2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 0.25, 0.25, 0.25, 0.25, 0.25];
+
+        let basis = sh_basis(normal.normalize());
+        let mut irradiance = Vec3::ZERO;
+        for i in 0..9 {
+            irradiance += self.coefficients[i] * BANDS[i] * basis[i];
+        }
+
+        irradiance.max(Vec3::ZERO)
+    }
+}
+
+fn radical_inverse(mut bits: u32) -> real {
+    bits = bits.reverse_bits();
+    bits as real / 4294967296.0
+}
+
+fn hammersley(i: usize, count: usize) -> Vec2 {
+    Vec2::new(i as real / count as real, radical_inverse(i as u32))
+}
+
+// Half vector around `normal` distributed like GGX's normal distribution
+fn importance_sample_ggx(xi: Vec2, normal: Vec3, roughness: real) -> Vec3 {
+    let a = roughness * roughness;
+    let phi = 2.0 * consts::PI * xi.x;
+    let cos_theta = ((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let up = if normal.z.abs() < 0.999 { Vec3::Z } else { Vec3::X };
+    let tangent = up.cross(normal).normalize();
+    let bitangent = normal.cross(tangent);
+
+    (tangent * (phi.cos() * sin_theta) + bitangent * (phi.sin() * sin_theta) + normal * cos_theta).normalize()
+}
+
+fn ggx_distribution(n_dot_h: real, roughness: real) -> real {
+    let a2 = roughness.powi(4);
+    let denominator = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+
+    a2 / (consts::PI * denominator * denominator)
+}
+
+// Mip level `l` out of `levels` holds the environment convolved for a roughness of l / (levels - 1).
+// The environment should have mipmaps, samples read blurrier levels to avoid fireflies.
+pub fn prefilter_specular(environment: &CubeMap, size: usize, levels: usize, samples: usize) -> Result<CubeMap, &'static str> {
+    let levels = levels.clamp(1, size.max(1).ilog2() as usize + 1);
+    let samples = samples.max(1);
+    let sampler = Sampler::default();
+    let source_size = environment.faces[0].width as real;
+    let texel_solid_angle = 4.0 * consts::PI / (6.0 * source_size * source_size);
+
+    let mut cube_levels = Vec::with_capacity(levels);
+    for level in 0..levels {
+        let roughness = if levels > 1 { level as real / (levels - 1) as real } else { 0.0 };
+
+        cube_levels.push(cube_map_from_fn((size >> level).max(1), |direction| {
+            let normal = direction.normalize();
+            if roughness == 0.0 {
+                return environment.sample(&sampler, normal);
+            }
+
+            // The view direction is assumed to be the normal
+            let mut color = Vec4::ZERO;
+            let mut weight = 0.0;
+            for i in 0..samples {
+                let half = importance_sample_ggx(hammersley(i, samples), normal, roughness);
+                let light = half * 2.0 * normal.dot(half) - normal;
+                let n_dot_l = normal.dot(light);
+                if n_dot_l <= 0.0 {
+                    continue;
+                }
+
+                let pdf = ggx_distribution(normal.dot(half).max(0.0), roughness) / 4.0;
+                let sample_solid_angle = 1.0 / (samples as real * pdf + 0.0001);
+                let lod = 0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0;
+
+                color += environment.sample_lod(&sampler, light, lod) * n_dot_l;
+                weight += n_dot_l;
+            }
+
+            if weight > 0.0 { color / weight } else { environment.sample(&sampler, normal) }
+        })?);
+    }
+
+    let mut cube_levels = cube_levels.into_iter();
+    let mut prefiltered = cube_levels.next().ok_or("No levels to prefilter!")?;
+    let mips: Vec<CubeMap> = cube_levels.collect();
+
+    for (i, face) in prefiltered.faces.iter_mut().enumerate() {
+        let mut face_mips = Vec::with_capacity(mips.len());
+        for mip in mips.iter() {
+            face_mips.push(mip.faces[i].region(0, 0, mip.faces[i].width, mip.faces[i].height)?);
+        }
+        face.set_mips(face_mips)?;
+    }
+
+    Ok(prefiltered)
+}
+
+fn geometry_schlick_ggx(n_dot_x: real, roughness: real) -> real {
+    let k = roughness * roughness / 2.0;
+    n_dot_x / (n_dot_x * (1.0 - k) + k)
+}
+
+// Scale (red) and bias (green) applied to F0 by the specular BRDF, for N·V along u and
+// roughness along v
+pub fn brdf_lut(size: usize, samples: usize) -> Result<Texture, &'static str> {
+    let samples = samples.max(1);
+    let mut data = Vec::with_capacity(size * size * 4);
+
+    for y in 0..size {
+        for x in 0..size {
+            let n_dot_v = ((x as real + 0.5) / size as real).max(0.001);
+            let roughness = (y as real + 0.5) / size as real;
+            let view = Vec3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+
+            let (mut scale, mut bias) = (0.0, 0.0);
+            for i in 0..samples {
+                let half = importance_sample_ggx(hammersley(i, samples), Vec3::Z, roughness);
+                let light = half * 2.0 * view.dot(half) - view;
+
+                let n_dot_l = light.z.max(0.0);
+                let n_dot_h = half.z.max(0.0);
+                let v_dot_h = view.dot(half).max(0.0);
+                if n_dot_l <= 0.0 {
+                    continue;
+                }
+
+                let geometry = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+                let visibility = geometry * v_dot_h / (n_dot_h * n_dot_v);
+                let fresnel = (1.0 - v_dot_h).powi(5);
+
+                scale += (1.0 - fresnel) * visibility;
+                bias += fresnel * visibility;
+            }
+
+            data.extend([to_f32(scale / samples as real), to_f32(bias / samples as real), 0.0, 1.0]);
+        }
+    }
+
+    Texture::from_rgba32f(size, size, data)
+}
+
+// Everything a shader needs for image based lighting
+pub struct Environment {
+    pub irradiance: SphericalHarmonics,
+    pub specular: CubeMap,
+    pub brdf: Texture,
+    pub sampler: Sampler,
+}
+
+impl Environment {
+    pub fn new(environment: &CubeMap, specular_size: usize, specular_levels: usize, samples: usize) -> Result<Environment, &'static str> {
+        Ok(Environment {
+            irradiance: SphericalHarmonics::from_cube_map(environment),
+            specular: prefilter_specular(environment, specular_size, specular_levels, samples)?,
+            brdf: brdf_lut(32, samples)?,
+            sampler: Sampler::new(Filter::Bilinear, Wrap::Clamp),
+        })
+    }
+
+    // Multiply by the albedo
+    pub fn diffuse(&self, normal: Vec3) -> Vec3 {
+        self.irradiance.irradiance(normal)
+    }
+
+    // `view` points from the surface towards the eye, `f0` is the reflectance at normal incidence
+    pub fn specular(&self, normal: Vec3, view: Vec3, f0: Vec3, roughness: real) -> Vec3 {
+        let (normal, view) = (normal.normalize(), view.normalize());
+        let n_dot_v = normal.dot(view).max(0.0);
+        let reflected = normal * 2.0 * n_dot_v - view;
+
+        let lod = roughness.clamp(0.0, 1.0) * (self.specular.levels() - 1) as real;
+        let radiance = self.specular.sample_lod(&self.sampler, reflected, lod).truncate();
+        let brdf = self.sampler.sample(&self.brdf, Vec2::new(n_dot_v, roughness));
+
+        radiance * (f0 * brdf.x + Vec3::splat(brdf.y))
+    }
+}
@@ -0,0 +1,157 @@
+use crate::formats::*;
+use crate::texture::*;
+
+const IDENTIFIER: [u8; 12] = [0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    read_u32(data, offset) as u64 | (read_u32(data, offset + 4) as u64) << 32
+}
+
+// VkFormat values, sRGB ones are decoded without conversion like `Texture::from_image`
+fn vk_format(format: u32) -> Result<TextureFormat, &'static str> {
+    Ok(match format {
+        37 | 43 => TextureFormat::Rgba8,
+        44 | 50 => TextureFormat::Bgra8,
+        131 | 132 => TextureFormat::Bc1Rgb,
+        133 | 134 => TextureFormat::Bc1,
+        135 | 136 => TextureFormat::Bc2,
+        137 | 138 => TextureFormat::Bc3,
+        139 => TextureFormat::Bc4,
+        140 => TextureFormat::Bc4Signed,
+        141 => TextureFormat::Bc5,
+        142 => TextureFormat::Bc5Signed,
+        143 => TextureFormat::Bc6h,
+        144 => TextureFormat::Bc6hSigned,
+        145 | 146 => TextureFormat::Bc7,
+        147 | 148 => TextureFormat::Etc2Rgb,
+        149 | 150 => TextureFormat::Etc2RgbA1,
+        151 | 152 => TextureFormat::Etc2Rgba,
+        153 => TextureFormat::EacR11,
+        154 => TextureFormat::EacR11Signed,
+        155 => TextureFormat::EacRg11,
+        156 => TextureFormat::EacRg11Signed,
+        _ => return Err("Unsupported VkFormat in KTX2 file!"),
+    })
+}
+
+// 2D KTX2 textures along with the mip levels stored in the file. Supercompressed files
+// (Basis Universal, Zstandard), cube maps, arrays and volumes aren't supported.
+pub fn from_ktx2(data: &[u8]) -> Result<Texture, &'static str> {
+    if data.len() < HEADER_SIZE || data[..12] != IDENTIFIER {
+        return Err("Not a KTX2 file!");
+    }
+
+    let format = vk_format(read_u32(data, 12))?;
+    let width = read_u32(data, 20) as usize;
+    let height = (read_u32(data, 24) as usize).max(1);
+    let depth = read_u32(data, 28);
+    let layers = read_u32(data, 32);
+    let faces = read_u32(data, 36);
+    // 0 asks the loader to generate mipmaps
+    let generate_mipmaps = read_u32(data, 40) == 0;
+
+    if read_u32(data, 44) != 0 {
+        return Err("Supercompressed KTX2 files aren't supported!");
+    }
+    if depth > 1 || layers > 1 || faces != 1 {
+        return Err("Only 2D KTX2 textures are supported!");
+    }
+    if width == 0 {
+        return Err("KTX2 texture has no texels!");
+    }
+
+    // No more levels than the size can halve down to
+    let levels = (read_u32(data, 40) as usize).clamp(1, width.max(height).ilog2() as usize + 1);
+
+    let mut level_data = Vec::with_capacity(levels);
+    for level in 0..levels {
+        let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+        if data.len() < entry + LEVEL_INDEX_ENTRY_SIZE {
+            return Err("KTX2 file is cut short!");
+        }
+
+        let offset = read_u64(data, entry) as usize;
+        let length = read_u64(data, entry + 8) as usize;
+        let end = offset.checked_add(length).ok_or("KTX2 file is cut short!")?;
+        level_data.push(data.get(offset..end).ok_or("KTX2 file is cut short!")?);
+    }
+
+    let mut texture = format.decode_levels(width, height, &level_data)?;
+    if generate_mipmaps {
+        texture.generate_mipmaps();
+    }
+
+    Ok(texture)
+}
+
+pub fn open_ktx2(path: impl AsRef<std::path::Path>) -> Result<Texture, &'static str> {
+    from_ktx2(&std::fs::read(path).map_err(|_| "Couldn't open the KTX2 file!")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+
+    // R8G8B8A8_UNORM levels, stored after the level index
+    fn ktx2(width: u32, height: u32, level_count: u32, levels: &[&[u8]]) -> Vec<u8> {
+        let mut data = IDENTIFIER.to_vec();
+        for value in [37, 1, width, height, 0, 0, 1, level_count, 0] {
+            data.extend(u32::to_le_bytes(value));
+        }
+        data.resize(HEADER_SIZE, 0);
+
+        let mut offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+        for level in levels {
+            for value in [offset, level.len(), level.len()] {
+                data.extend(u64::to_le_bytes(value as u64));
+            }
+            offset += level.len();
+        }
+        for level in levels {
+            data.extend(*level);
+        }
+
+        data
+    }
+
+    #[test]
+    fn rgba8_with_mips() {
+        let level_0 = [[10, 20, 30, 255], [40, 50, 60, 128], [70, 80, 90, 0], [100, 110, 120, 255]].concat();
+        let level_1 = [[5, 6, 7, 8], [9, 10, 11, 12]].concat();
+        let level_2 = [1, 2, 3, 4];
+        let data = ktx2(4, 1, 3, &[&level_0, &level_1, &level_2]);
+
+        let texture = from_ktx2(&data).unwrap();
+        assert_eq!((texture.width, texture.height, texture.levels()), (4, 1, 3));
+        assert_eq!(texture.texel(1, 0), Vec4::new(40.0, 50.0, 60.0, 128.0) / 255.0);
+        assert_eq!(texture.texel(3, 0), Vec4::new(100.0, 110.0, 120.0, 255.0) / 255.0);
+        assert_eq!(texture.level(1).texel(1, 0), Vec4::new(9.0, 10.0, 11.0, 12.0) / 255.0);
+        assert_eq!(texture.level(2).texel(0, 0), Vec4::new(1.0, 2.0, 3.0, 4.0) / 255.0);
+
+        assert!(from_ktx2(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn generated_mips() {
+        let level_0 = [[0, 0, 0, 255], [254, 254, 254, 255]].concat();
+        let texture = from_ktx2(&ktx2(2, 1, 0, &[&level_0])).unwrap();
+
+        assert_eq!(texture.levels(), 2);
+        assert_eq!(texture.level(1).texel(0, 0), Vec4::new(127.0, 127.0, 127.0, 255.0) / 255.0);
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        assert!(from_ktx2(&ktx2(0, 5, 0, &[&[]])).is_err());
+        assert!(from_ktx2(&ktx2(0xffffffff, 0xffffffff, 1, &[&[0; 16]])).is_err());
+        // More levels than the size allows, the index isn't read past the first
+        assert_eq!(from_ktx2(&ktx2(1, 1, 0xffffffff, &[&[0; 4]])).unwrap().levels(), 1);
+    }
+}
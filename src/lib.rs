@@ -0,0 +1,20 @@
+pub mod array;
+pub mod buffer;
+pub mod clip;
+pub mod cubemap;
+pub mod dds;
+pub mod formats;
+pub mod hiz;
+pub mod ibl;
+pub mod ktx2;
+pub mod noise;
+pub mod occlusion;
+pub mod oit;
+pub mod peel;
+pub mod shader;
+pub mod shadow;
+pub mod state;
+pub mod tangent;
+pub mod texture;
+pub mod utils;
+pub mod volume;
@@ -1,14 +1,11 @@
 use minifb::*;
 // use noise::{NoiseFn, Perlin};
-use glam::Vec4Swizzles;
-use image::GenericImageView;
 
-mod buffer;
-mod utils;
-mod shader;
-use crate::utils::*;
-use crate::buffer::*;
-use crate::shader::*;
+use rust_software_rendering::utils::*;
+use rust_software_rendering::buffer::*;
+use rust_software_rendering::shader::*;
+use rust_software_rendering::state::*;
+use rust_software_rendering::texture::*;
 
 use std::time::Instant;
 
@@ -16,13 +13,16 @@ struct SimpleShader {
     pub t: real,
     pub light: Vec3,
     pub camera: Mat4,
-    pub img: image::DynamicImage
+    pub texture: Texture,
+    pub sampler: Sampler
 }
 
 impl Shader for SimpleShader {
     type Vertex = (Vec3, Vec3, Vec2);
     type VertexShaderOut = (Vec3, Vec2);
 
+    const DERIVATIVES: bool = true;
+
     fn vertex(&self, vertex: &Self::Vertex) -> (Vec4, Self::VertexShaderOut) {
         let (pos, normal, tex_pos) = *vertex;
 
@@ -38,20 +38,15 @@ impl Shader for SimpleShader {
     fn fragment(&self, varyings: &Self::VertexShaderOut) -> Vec4 {
         let (normal, tex_pos) = *varyings;
 
-        let pixel = self.img.get_pixel(
-            (tex_pos.x * (self.img.width() as real - 0.001)) as u32,
-            (tex_pos.y * (self.img.height() as real - 0.001)) as u32
-        ).0;
+        let light = normal.dot(self.light).max(0.0) + 0.4;
+        self.sampler.sample(&self.texture, tex_pos) * light
+    }
 
-        let pixel = Vec4::new(
-            pixel[0] as real,
-            pixel[1] as real,
-            pixel[2] as real,
-            pixel[3] as real,
-        ) / 255.0;
+    fn fragment_grad(&self, varyings: &Self::VertexShaderOut, ddx: &Self::VertexShaderOut, ddy: &Self::VertexShaderOut) -> Vec4 {
+        let (normal, tex_pos) = *varyings;
 
         let light = normal.dot(self.light).max(0.0) + 0.4;
-        pixel * light
+        self.sampler.sample_grad(&self.texture, tex_pos, ddx.1, ddy.1) * light
     }
 }
 
@@ -72,6 +67,9 @@ fn main() {
 
     // window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
 
+    let mut texture = Texture::from_image(&image::open("crate.jpg").unwrap());
+    texture.generate_mipmaps();
+
     let mut shader = SimpleShader{
         t: 0.0,
         light: Vec3::new(0.5, 1.2, 0.8).normalize(),
@@ -84,18 +82,24 @@ fn main() {
             Vec3::new(0.0, 0.0, 1.0),
             Vec3::new(0.0, 1.0, 0.0),
         ),
-        img: image::open("crate.jpg").unwrap()
+        texture,
+        sampler: Sampler {
+            max_anisotropy: 4,
+            ..Sampler::new(Filter::Bilinear, Wrap::Clamp)
+        }
     };
 
     let mut i = 0;
     let mut delta = 0.0;
+    let mut draw_state = DrawState::default();
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
         let now = Instant::now();
         i = (i + 1) % 100;
         
         frame_buffer.clear();
-        shader.draw(&mut frame_buffer, &[
+        draw_state.stats = Some(Stats::default());
+        shader.draw_with(&mut frame_buffer, &[
             (Vec3::new(-0.5,  0.5,  0.5), Vec3::new(0.0, 0.0, 1.0), Vec2::new(0.0, 0.0)),
             (Vec3::new(-0.5, -0.5,  0.5), Vec3::new(0.0, 0.0, 1.0), Vec2::new(1.0, 0.0)),
             (Vec3::new( 0.5,  0.5,  0.5), Vec3::new(0.0, 0.0, 1.0), Vec2::new(0.0, 1.0)),
@@ -148,7 +152,7 @@ fn main() {
 
             20, 21, 22,
             23, 21, 22,
-        ]);
+        ], &mut draw_state).unwrap();
 
         frame_buffer.fill_window_buffer(&mut window_buffer, window.is_key_down(Key::D)).unwrap();
         window.update_with_buffer(&window_buffer, window_size.0, window_size.1).unwrap();
@@ -160,11 +164,15 @@ fn main() {
         // }
 
         shader.t += 0.1 * delta;
-        shader.t = shader.t % (2.0 * 3.14);
+        shader.t %= 2.0 * consts::PI;
 
-        delta = now.elapsed().as_secs_f64();
+        delta = now.elapsed().as_secs_f64() as real;
         if i == 0 {
-            println!("{:.3} fps", 1.0 / delta);
+            let stats = draw_state.stats.unwrap_or_default();
+            println!(
+                "{:.3} fps, {} fragments shaded, {} pixels written",
+                1.0 / delta, stats.fragments_shaded, stats.pixels_written
+            );
         }
     }
 }
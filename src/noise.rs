@@ -0,0 +1,367 @@
+use crate::utils::*;
+
+// Gradient, value and cellular noise from a seeded permutation table. Everything takes `&self`,
+// so a `Noise` can live in a shader and be called from its fragment functions.
+#[derive(Clone)]
+pub struct Noise {
+    permutation: [u8; 512],
+}
+
+impl Default for Noise {
+    fn default() -> Noise {
+        Noise::new(0)
+    }
+}
+
+const GRADIENTS_2: [(real, real); 8] = [
+    (1.0, 1.0), (-1.0, 1.0), (1.0, -1.0), (-1.0, -1.0),
+    (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0),
+];
+
+// Edges of a cube, as in improved Perlin noise
+const GRADIENTS_3: [(real, real, real); 12] = [
+    (1.0, 1.0, 0.0), (-1.0, 1.0, 0.0), (1.0, -1.0, 0.0), (-1.0, -1.0, 0.0),
+    (1.0, 0.0, 1.0), (-1.0, 0.0, 1.0), (1.0, 0.0, -1.0), (-1.0, 0.0, -1.0),
+    (0.0, 1.0, 1.0), (0.0, -1.0, 1.0), (0.0, 1.0, -1.0), (0.0, -1.0, -1.0),
+];
+
+fn fade(t: real) -> real {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: real, b: real, t: real) -> real {
+    a + (b - a) * t
+}
+
+fn gradient_2(hash: u8, x: real, y: real) -> real {
+    let (gx, gy) = GRADIENTS_2[hash as usize % 8];
+    gx * x + gy * y
+}
+
+fn gradient_3(hash: u8, x: real, y: real, z: real) -> real {
+    let (gx, gy, gz) = GRADIENTS_3[hash as usize % 12];
+    gx * x + gy * y + gz * z
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Noise {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+
+        // Fisher-Yates with splitmix64
+        let mut state = seed;
+        for i in (1..256).rev() {
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            table.swap(i, ((z ^ (z >> 31)) % (i as u64 + 1)) as usize);
+        }
+
+        Noise { permutation: std::array::from_fn(|i| table[i % 256]) }
+    }
+
+    fn hash_2(&self, x: i64, y: i64) -> u8 {
+        let p = &self.permutation;
+        p[p[(x & 255) as usize] as usize + (y & 255) as usize]
+    }
+
+    fn hash_3(&self, x: i64, y: i64, z: i64) -> u8 {
+        let p = &self.permutation;
+        p[self.hash_2(x, y) as usize + (z & 255) as usize]
+    }
+
+    // Roughly [-1, 1], 0 at integer coordinates. Repeats every 256 units.
+    pub fn perlin_2(&self, p: Vec2) -> real {
+        let cell = p.floor();
+        let (x, y) = (cell.x as i64, cell.y as i64);
+        let f = p - cell;
+        let (u, v) = (fade(f.x), fade(f.y));
+
+        lerp(
+            lerp(gradient_2(self.hash_2(x, y), f.x, f.y), gradient_2(self.hash_2(x + 1, y), f.x - 1.0, f.y), u),
+            lerp(gradient_2(self.hash_2(x, y + 1), f.x, f.y - 1.0), gradient_2(self.hash_2(x + 1, y + 1), f.x - 1.0, f.y - 1.0), u),
+            v
+        )
+    }
+
+    pub fn perlin_3(&self, p: Vec3) -> real {
+        let cell = p.floor();
+        let (x, y, z) = (cell.x as i64, cell.y as i64, cell.z as i64);
+        let f = p - cell;
+        let (u, v, w) = (fade(f.x), fade(f.y), fade(f.z));
+
+        let corner = |dx: i64, dy: i64, dz: i64| gradient_3(
+            self.hash_3(x + dx, y + dy, z + dz),
+            f.x - dx as real, f.y - dy as real, f.z - dz as real
+        );
+
+        lerp(
+            lerp(lerp(corner(0, 0, 0), corner(1, 0, 0), u), lerp(corner(0, 1, 0), corner(1, 1, 0), u), v),
+            lerp(lerp(corner(0, 0, 1), corner(1, 0, 1), u), lerp(corner(0, 1, 1), corner(1, 1, 1), u), v),
+            w
+        )
+    }
+
+    // Roughly [-1, 1], cheaper than Perlin noise in 3D and without its axis aligned artifacts
+    pub fn simplex_2(&self, p: Vec2) -> real {
+        let skew = (3.0 as real).sqrt();
+        let (f2, g2) = (0.5 * (skew - 1.0), (3.0 - skew) / 6.0);
+
+        let s = (p.x + p.y) * f2;
+        let cell = (p + Vec2::splat(s)).floor();
+        let t = (cell.x + cell.y) * g2;
+        let p0 = p - (cell - Vec2::splat(t));
+
+        let offset = if p0.x > p0.y { Vec2::X } else { Vec2::Y };
+        let corners = [p0, p0 - offset + Vec2::splat(g2), p0 - Vec2::ONE + Vec2::splat(2.0 * g2)];
+        let cells = [Vec2::ZERO, offset, Vec2::ONE];
+
+        let mut total = 0.0;
+        for (corner, offset) in corners.iter().zip(cells) {
+            let falloff = 0.5 - corner.length_squared();
+            if falloff > 0.0 {
+                let hash = self.hash_2(cell.x as i64 + offset.x as i64, cell.y as i64 + offset.y as i64);
+                total += falloff.powi(4) * gradient_2(hash, corner.x, corner.y);
+            }
+        }
+
+        70.0 * total
+    }
+
+    pub fn simplex_3(&self, p: Vec3) -> real {
+        let (f3, g3) = (1.0 / 3.0, 1.0 / 6.0);
+
+        let s = (p.x + p.y + p.z) * f3;
+        let cell = (p + Vec3::splat(s)).floor();
+        let t = (cell.x + cell.y + cell.z) * g3;
+        let p0 = p - (cell - Vec3::splat(t));
+
+        // Which of the six tetrahedra in the skewed cube the point is in
+        let (first, second) = if p0.x >= p0.y {
+            if p0.y >= p0.z {
+                (Vec3::X, Vec3::new(1.0, 1.0, 0.0))
+            } else if p0.x >= p0.z {
+                (Vec3::X, Vec3::new(1.0, 0.0, 1.0))
+            } else {
+                (Vec3::Z, Vec3::new(1.0, 0.0, 1.0))
+            }
+        } else if p0.y < p0.z {
+            (Vec3::Z, Vec3::new(0.0, 1.0, 1.0))
+        } else if p0.x < p0.z {
+            (Vec3::Y, Vec3::new(0.0, 1.0, 1.0))
+        } else {
+            (Vec3::Y, Vec3::new(1.0, 1.0, 0.0))
+        };
+
+        let cells = [Vec3::ZERO, first, second, Vec3::ONE];
+        let mut total = 0.0;
+        for (i, offset) in cells.iter().enumerate() {
+            let corner = p0 - *offset + Vec3::splat(g3 * i as real);
+            let falloff = 0.6 - corner.length_squared();
+            if falloff > 0.0 {
+                let hash = self.hash_3(
+                    cell.x as i64 + offset.x as i64,
+                    cell.y as i64 + offset.y as i64,
+                    cell.z as i64 + offset.z as i64
+                );
+                total += falloff.powi(4) * gradient_3(hash, corner.x, corner.y, corner.z);
+            }
+        }
+
+        32.0 * total
+    }
+
+    // [-1, 1], random values at integer coordinates smoothly blended in between
+    pub fn value_2(&self, p: Vec2) -> real {
+        let cell = p.floor();
+        let (x, y) = (cell.x as i64, cell.y as i64);
+        let f = p - cell;
+        let (u, v) = (fade(f.x), fade(f.y));
+        let value = |dx: i64, dy: i64| self.hash_2(x + dx, y + dy) as real / 127.5 - 1.0;
+
+        lerp(lerp(value(0, 0), value(1, 0), u), lerp(value(0, 1), value(1, 1), u), v)
+    }
+
+    pub fn value_3(&self, p: Vec3) -> real {
+        let cell = p.floor();
+        let (x, y, z) = (cell.x as i64, cell.y as i64, cell.z as i64);
+        let f = p - cell;
+        let (u, v, w) = (fade(f.x), fade(f.y), fade(f.z));
+        let value = |dx: i64, dy: i64, dz: i64| self.hash_3(x + dx, y + dy, z + dz) as real / 127.5 - 1.0;
+
+        lerp(
+            lerp(lerp(value(0, 0, 0), value(1, 0, 0), u), lerp(value(0, 1, 0), value(1, 1, 0), u), v),
+            lerp(lerp(value(0, 0, 1), value(1, 0, 1), u), lerp(value(0, 1, 1), value(1, 1, 1), u), v),
+            w
+        )
+    }
+
+    // Cellular noise: distances to the closest and second closest of one random point per
+    // unit cell (F1, F2)
+    pub fn worley_2(&self, p: Vec2) -> (real, real) {
+        let cell = p.floor();
+        let (x, y) = (cell.x as i64, cell.y as i64);
+        let (mut f1, mut f2) = (real::INFINITY, real::INFINITY);
+
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let hash = self.hash_2(x + dx, y + dy) as usize;
+                let jitter = Vec2::new(self.permutation[hash] as real, self.permutation[hash + 1] as real) / 255.0;
+                let distance = (cell + Vec2::new(dx as real, dy as real) + jitter).distance(p);
+
+                if distance < f1 {
+                    f2 = f1;
+                    f1 = distance;
+                } else if distance < f2 {
+                    f2 = distance;
+                }
+            }
+        }
+
+        (f1, f2)
+    }
+
+    pub fn worley_3(&self, p: Vec3) -> (real, real) {
+        let cell = p.floor();
+        let (x, y, z) = (cell.x as i64, cell.y as i64, cell.z as i64);
+        let (mut f1, mut f2) = (real::INFINITY, real::INFINITY);
+
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let hash = self.hash_3(x + dx, y + dy, z + dz) as usize;
+                    let jitter = Vec3::new(
+                        self.permutation[hash] as real,
+                        self.permutation[hash + 1] as real,
+                        self.permutation[hash + 2] as real
+                    ) / 255.0;
+                    let distance = (cell + Vec3::new(dx as real, dy as real, dz as real) + jitter).distance(p);
+
+                    if distance < f1 {
+                        f2 = f1;
+                        f1 = distance;
+                    } else if distance < f2 {
+                        f2 = distance;
+                    }
+                }
+            }
+        }
+
+        (f1, f2)
+    }
+}
+
+// Octaves of a noise function, each `lacunarity` times the frequency and `gain` times the
+// amplitude of the one before
+#[derive(Clone, Copy, Debug)]
+pub struct Fractal {
+    pub octaves: usize,
+    pub lacunarity: real,
+    pub gain: real,
+}
+
+impl Default for Fractal {
+    fn default() -> Fractal {
+        Fractal { octaves: 5, lacunarity: 2.0, gain: 0.5 }
+    }
+}
+
+impl Fractal {
+    // Fractional Brownian motion, normalized to the noise's range, e.g.
+    // `fractal.fbm(p, |p| noise.perlin_3(p))`
+    pub fn fbm<P: NumLike>(&self, p: P, noise: impl Fn(P) -> real) -> real {
+        self.sum(p, noise, |value| value)
+    }
+
+    // Sum of absolute values, [0, 1] for noise in [-1, 1]. Billowy, e.g. for clouds and fire.
+    pub fn turbulence<P: NumLike>(&self, p: P, noise: impl Fn(P) -> real) -> real {
+        self.sum(p, noise, real::abs)
+    }
+
+    fn sum<P: NumLike>(&self, p: P, noise: impl Fn(P) -> real, shape: impl Fn(real) -> real) -> real {
+        let (mut total, mut total_amplitude) = (0.0, 0.0);
+        let (mut frequency, mut amplitude) = (1.0, 1.0);
+
+        for _ in 0..self.octaves {
+            total += shape(noise(p * frequency)) * amplitude;
+            total_amplitude += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+
+        if total_amplitude > 0.0 { total / total_amplitude } else { 0.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_2() -> impl Iterator<Item = Vec2> {
+        (0..64 * 64).map(|i| Vec2::new((i % 64) as real, (i / 64) as real) * 0.37 - Vec2::splat(11.0))
+    }
+
+    fn grid_3() -> impl Iterator<Item = Vec3> {
+        (0..16 * 16 * 16).map(|i| Vec3::new((i % 16) as real, (i / 16 % 16) as real, (i / 256) as real) * 0.43 - Vec3::splat(3.0))
+    }
+
+    #[test]
+    fn perlin_is_zero_on_the_lattice() {
+        let noise = Noise::new(7);
+
+        for y in -5..5 {
+            for x in -5..5 {
+                assert_eq!(noise.perlin_2(Vec2::new(x as real, y as real)), 0.0);
+                assert_eq!(noise.perlin_3(Vec3::new(x as real, y as real, (x - y) as real)), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let noise = Noise::new(3);
+        let in_range = |value: real| (-1.0..=1.0).contains(&value);
+
+        for p in grid_2() {
+            assert!(in_range(noise.perlin_2(p)) && in_range(noise.simplex_2(p)) && in_range(noise.value_2(p)), "{:?}", p);
+        }
+        for p in grid_3() {
+            assert!(in_range(noise.perlin_3(p)) && in_range(noise.simplex_3(p)) && in_range(noise.value_3(p)), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn worley_distances_are_ordered() {
+        let noise = Noise::new(11);
+
+        for p in grid_2() {
+            let (f1, f2) = noise.worley_2(p);
+            assert!(0.0 <= f1 && f1 <= f2, "{:?}", p);
+        }
+        for p in grid_3() {
+            let (f1, f2) = noise.worley_3(p);
+            assert!(0.0 <= f1 && f1 <= f2, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn seeds() {
+        let (a, b, other) = (Noise::new(42), Noise::new(42), Noise::new(43));
+
+        assert!(grid_3().all(|p| a.simplex_3(p) == b.simplex_3(p) && a.worley_3(p) == b.worley_3(p)));
+        assert!(grid_2().all(|p| a.perlin_2(p) == b.perlin_2(p) && a.value_2(p) == b.value_2(p)));
+        assert!(grid_2().any(|p| a.perlin_2(p) != other.perlin_2(p)));
+    }
+
+    #[test]
+    fn turbulence_range() {
+        let noise = Noise::default();
+        let fractal = Fractal::default();
+
+        for p in grid_3() {
+            let value = fractal.turbulence(p, |p| noise.simplex_3(p));
+            assert!((0.0..=1.0).contains(&value), "{:?}", p);
+            assert!((-1.0..=1.0).contains(&fractal.fbm(p, |p| noise.perlin_3(p))), "{:?}", p);
+        }
+    }
+}
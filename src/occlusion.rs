@@ -0,0 +1,248 @@
+use crate::utils::*;
+
+use glam::Vec3Swizzles;
+
+pub const OCCLUSION_TILE_SIZE: usize = 8;
+
+// One bit per pixel of a tile
+type Mask = u64;
+
+// Masked occlusion culling: every tile keeps a conservative depth for all its pixels (`z0`)
+// and a working layer made of a coverage mask and the depth of the occluders behind it (`z1`).
+// Once the working layer covers the whole tile it replaces `z0`. Depths follow the depth test
+// of `Buffer`: greater values are in front.
+#[derive(Clone, Copy)]
+struct OcclusionTile {
+    mask: Mask,
+    // Pixels of the tile inside the buffer, partial tiles at the borders can't be covered entirely
+    full: Mask,
+    z0: f32,
+    z1: f32,
+}
+
+pub struct OcclusionBuffer {
+    tiles: Vec<OcclusionTile>,
+    tiles_x: usize,
+    tiles_y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl OcclusionBuffer {
+    pub fn new(width: usize, height: usize) -> OcclusionBuffer {
+        let tiles_x = width.div_ceil(OCCLUSION_TILE_SIZE);
+        let tiles_y = height.div_ceil(OCCLUSION_TILE_SIZE);
+        let mut tiles = Vec::with_capacity(tiles_x * tiles_y);
+
+        for tile_y in 0..tiles_y {
+            for tile_x in 0..tiles_x {
+                let mut full = 0;
+                for bit in 0..OCCLUSION_TILE_SIZE * OCCLUSION_TILE_SIZE {
+                    let x = tile_x * OCCLUSION_TILE_SIZE + bit % OCCLUSION_TILE_SIZE;
+                    let y = tile_y * OCCLUSION_TILE_SIZE + bit / OCCLUSION_TILE_SIZE;
+                    if x < width && y < height {
+                        full |= 1 << bit;
+                    }
+                }
+
+                tiles.push(OcclusionTile {
+                    mask: 0,
+                    full,
+                    z0: f32::NEG_INFINITY,
+                    z1: f32::INFINITY,
+                });
+            }
+        }
+
+        OcclusionBuffer {
+            tiles, tiles_x, tiles_y, width, height
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for tile in self.tiles.iter_mut() {
+            tile.mask = 0;
+            tile.z0 = f32::NEG_INFINITY;
+            tile.z1 = f32::INFINITY;
+        }
+    }
+
+    // Occluders are transformed by `matrix` like vertices by `Shader::vertex`.
+    // Coverage is sampled at pixel positions on the same grid `Shader::draw` snaps vertices to.
+    pub fn draw_occluder(&mut self, matrix: Mat4, positions: &[Vec3], indices: &[usize]) {
+        for triangle_indices in indices.chunks_exact(3) {
+            let mut triangle = [Vec3::ZERO; 3];
+            for (corner, &index) in triangle.iter_mut().zip(triangle_indices) {
+                *corner = screen_to_buffer_space(matrix * positions[index].extend(1.0), self.width, self.height);
+            }
+
+            if triangle.iter().all(|p| p.is_finite()) {
+                self.rasterize(triangle);
+            }
+        }
+    }
+
+    fn rasterize(&mut self, triangle: [Vec3; 3]) {
+        let [p0, mut p1, mut p2] = triangle;
+
+        let mut area = (p1 - p0).xy().perp_dot((p2 - p0).xy());
+        if area == 0.0 {
+            return;
+        }
+        if area < 0.0 {
+            std::mem::swap(&mut p1, &mut p2);
+            area = -area;
+        }
+
+        let min = p0.min(p1).min(p2);
+        let max = p0.max(p1).max(p2);
+        if max.x <= 0.0 || max.y <= 0.0 || min.x >= self.width as real || min.y >= self.height as real {
+            return;
+        }
+
+        // Depth as a plane over the screen, to bound it per tile
+        let depth_dx = ((p1.z - p0.z) * (p2.y - p0.y) - (p2.z - p0.z) * (p1.y - p0.y)) / area;
+        let depth_dy = ((p2.z - p0.z) * (p1.x - p0.x) - (p1.z - p0.z) * (p2.x - p0.x)) / area;
+        let depth_at = |x: real, y: real| p0.z + (x - p0.x) * depth_dx + (y - p0.y) * depth_dy;
+
+        // Pixels exactly on an edge belong to only one of the triangles sharing it
+        let edges = [(p0, p1), (p1, p2), (p2, p0)];
+        let inside = |x: real, y: real| edges.iter().all(|(a, b)| {
+            let direction = (*b - *a).xy();
+            let distance = direction.perp_dot(Vec2::new(x, y) - a.xy());
+            distance > 0.0 || (distance == 0.0 && (direction.y > 0.0 || (direction.y == 0.0 && direction.x < 0.0)))
+        });
+
+        let tile_size = OCCLUSION_TILE_SIZE as real;
+        let tile_x0 = (min.x.max(0.0) / tile_size) as usize;
+        let tile_y0 = (min.y.max(0.0) / tile_size) as usize;
+        let tile_x1 = ((max.x / tile_size) as usize).min(self.tiles_x - 1);
+        let tile_y1 = ((max.y / tile_size) as usize).min(self.tiles_y - 1);
+
+        for tile_y in tile_y0..=tile_y1 {
+            for tile_x in tile_x0..=tile_x1 {
+                let x0 = (tile_x * OCCLUSION_TILE_SIZE) as real;
+                let y0 = (tile_y * OCCLUSION_TILE_SIZE) as real;
+
+                let mut mask: Mask = 0;
+                for bit in 0..OCCLUSION_TILE_SIZE * OCCLUSION_TILE_SIZE {
+                    let x = x0 + (bit % OCCLUSION_TILE_SIZE) as real;
+                    let y = y0 + (bit / OCCLUSION_TILE_SIZE) as real;
+                    if inside(x, y) {
+                        mask |= 1 << bit;
+                    }
+                }
+
+                let tile = &mut self.tiles[tile_y * self.tiles_x + tile_x];
+                mask &= tile.full;
+                if mask == 0 {
+                    continue;
+                }
+
+                // Lowest depth of the triangle over the part of the tile it can touch
+                let (x1, y1) = ((x0 + tile_size).min(max.x), (y0 + tile_size).min(max.y));
+                let (x0, y0) = (x0.max(min.x), y0.max(min.y));
+                let plane_min = depth_at(x0, y0).min(depth_at(x1, y0)).min(depth_at(x0, y1)).min(depth_at(x1, y1));
+                let depth = to_f32(plane_min.max(min.z));
+
+                update_tile(tile, mask, depth);
+            }
+        }
+    }
+
+    // Conservative: false only if the whole box is off-screen or behind the occluders drawn so far
+    pub fn aabb_visible(&self, matrix: Mat4, min: Vec3, max: Vec3) -> bool {
+        let (screen_min, screen_max) = match project_aabb(matrix, min, max, self.width, self.height) {
+            Some(bounds) => bounds,
+            None => return true,
+        };
+
+        if screen_max.x <= 0.0 || screen_max.y <= 0.0
+            || screen_min.x >= self.width as real || screen_min.y >= self.height as real {
+            return false;
+        }
+
+        let tile_size = OCCLUSION_TILE_SIZE as real;
+        let tile_x0 = (screen_min.x.max(0.0) / tile_size) as usize;
+        let tile_y0 = (screen_min.y.max(0.0) / tile_size) as usize;
+        let tile_x1 = ((screen_max.x / tile_size) as usize).min(self.tiles_x - 1);
+        let tile_y1 = ((screen_max.y / tile_size) as usize).min(self.tiles_y - 1);
+        let depth = to_f32(screen_max.z);
+
+        for tile_y in tile_y0..=tile_y1 {
+            for tile_x in tile_x0..=tile_x1 {
+                if self.tiles[tile_y * self.tiles_x + tile_x].z0 < depth {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+fn update_tile(tile: &mut OcclusionTile, mask: Mask, depth: f32) {
+    // A working layer far in front of the new triangle is dropped, merging them would only loosen z0
+    let distance_to_working = tile.z1 - depth;
+    let distance_to_reference = tile.z1 - tile.z0;
+    if distance_to_working > distance_to_reference {
+        tile.z1 = f32::INFINITY;
+        tile.mask = 0;
+    }
+
+    tile.z1 = tile.z1.min(depth);
+    tile.mask |= mask;
+
+    if tile.mask == tile.full {
+        tile.z0 = tile.z0.max(tile.z1);
+        tile.z1 = f32::INFINITY;
+        tile.mask = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rectangle from x0 to x1 in normalized device coordinates, over the buffer's whole height
+    fn draw_rect(buffer: &mut OcclusionBuffer, x0: real, x1: real, depth: real) {
+        let corners = [
+            Vec3::new(x0, -1.5, depth),
+            Vec3::new(x1, -1.5, depth),
+            Vec3::new(x0, 1.5, depth),
+            Vec3::new(x1, 1.5, depth),
+        ];
+        buffer.draw_occluder(Mat4::IDENTITY, &corners, &[0, 1, 2, 3, 2, 1]);
+    }
+
+    fn box_visible(buffer: &OcclusionBuffer, depth: real) -> bool {
+        buffer.aabb_visible(Mat4::IDENTITY, Vec3::new(-0.5, -0.5, depth - 0.01), Vec3::new(0.5, 0.5, depth))
+    }
+
+    #[test]
+    fn layers_merge_in_either_order() {
+        for (first, second) in [(0.9, 0.3), (0.3, 0.9)] {
+            let mut buffer = OcclusionBuffer::new(OCCLUSION_TILE_SIZE, OCCLUSION_TILE_SIZE);
+            draw_rect(&mut buffer, -1.5, 1.5, 0.1);
+            assert!(!box_visible(&buffer, 0.05));
+            assert!(box_visible(&buffer, 0.2));
+
+            draw_rect(&mut buffer, -1.5, 0.0, first);
+            draw_rect(&mut buffer, 0.0, 1.5, second);
+            assert!(!box_visible(&buffer, 0.25), "{} {}", first, second);
+            assert!(box_visible(&buffer, 0.35));
+        }
+    }
+
+    #[test]
+    fn near_then_far_keeps_the_near_occlusion() {
+        let mut buffer = OcclusionBuffer::new(OCCLUSION_TILE_SIZE, OCCLUSION_TILE_SIZE);
+        draw_rect(&mut buffer, -1.5, 1.5, 0.8);
+
+        // Entirely behind what's already there, it can't loosen the tile
+        draw_rect(&mut buffer, -1.5, 0.0, 0.1);
+        draw_rect(&mut buffer, 0.0, 1.5, 0.2);
+        assert!(!box_visible(&buffer, 0.75));
+        assert!(box_visible(&buffer, 0.85));
+    }
+}
@@ -0,0 +1,67 @@
+struct Fragment {
+    color: [f32; 4],
+    depth: f32,
+    next: Option<usize>,
+}
+
+// Per pixel linked lists of transparent fragments (an A-buffer), sorted and composited
+// once everything has been drawn
+pub struct FragmentLists {
+    heads: Vec<Option<usize>>,
+    fragments: Vec<Fragment>,
+    pixels: usize,
+}
+
+impl FragmentLists {
+    pub fn new(pixels: usize) -> FragmentLists {
+        // Heads are only allocated once something is pushed
+        FragmentLists {
+            heads: Vec::new(),
+            fragments: Vec::new(),
+            pixels
+        }
+    }
+
+    pub fn clear(&mut self) {
+        if !self.fragments.is_empty() {
+            self.heads.fill(None);
+            self.fragments.clear();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fragments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fragments.is_empty()
+    }
+
+    pub fn push(&mut self, pixel: usize, color: [f32; 4], depth: f32) {
+        if self.heads.is_empty() {
+            self.heads = vec![None; self.pixels];
+        }
+
+        self.fragments.push(Fragment {
+            color,
+            depth,
+            next: self.heads[pixel],
+        });
+        self.heads[pixel] = Some(self.fragments.len() - 1);
+    }
+
+    // Fragments of `pixel` back to front (increasing depth), submission order between equal depths
+    pub fn sorted(&self, pixel: usize, sorted: &mut Vec<(f32, [f32; 4])>) {
+        sorted.clear();
+
+        let mut next = self.heads.get(pixel).copied().flatten();
+        while let Some(index) = next {
+            let fragment = &self.fragments[index];
+            sorted.push((fragment.depth, fragment.color));
+            next = fragment.next;
+        }
+
+        sorted.reverse();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+}
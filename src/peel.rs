@@ -0,0 +1,72 @@
+use crate::buffer::*;
+use crate::state::*;
+
+// Renders the scene once per layer. Every pass only keeps fragments behind the previous layer,
+// so the depth test leaves the closest of the remaining ones. Layers come out front to back,
+// stopping early once a pass writes nothing.
+pub fn depth_peel(width: usize, height: usize, layers: usize, mut render: impl FnMut(&mut Buffer, &mut DrawState)) -> Vec<Buffer> {
+    let mut peeled: Vec<Buffer> = Vec::with_capacity(layers);
+    let mut previous = None;
+
+    for _ in 0..layers {
+        let mut layer = Buffer::new(width, height, 4);
+        layer.clear();
+
+        let mut state = DrawState {
+            stats: Some(Stats::default()),
+            peel: previous.take(),
+            ..Default::default()
+        };
+        render(&mut layer, &mut state);
+
+        if let Some(previous) = state.peel.take() {
+            peeled.push(previous);
+        }
+
+        if state.stats.map_or(0, |stats| stats.pixels_written) == 0 {
+            break;
+        }
+        previous = Some(layer);
+    }
+
+    if let Some(last) = previous {
+        peeled.push(last);
+    }
+
+    peeled
+}
+
+// Blends the layers back to front with the same opacity each, into a new buffer holding
+// the front layer's depth
+pub fn composite_layers(layers: &[Buffer], opacity: f32) -> Result<Buffer, &'static str> {
+    let front = layers.first().ok_or("No layers to composite!")?;
+    let (width, height) = (front.width, front.height);
+
+    if layers.iter().any(|layer| layer.width != width || layer.height != height || layer.depth != 4) {
+        return Err("Layers don't share the same size!");
+    }
+
+    let mut composite = Buffer::new(width, height, 4);
+    composite.clear();
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut color = [0.0; 3];
+
+            for layer in layers.iter().rev() {
+                let pixel = layer.get_pixel(x, y);
+                if pixel[3] == f32::NEG_INFINITY {
+                    continue;
+                }
+
+                for channel in 0..3 {
+                    color[channel] = pixel[channel] * opacity + color[channel] * (1.0 - opacity);
+                }
+            }
+
+            composite.set_pixel(x, y, &[color[0], color[1], color[2], front.get_depth(x, y)])?;
+        }
+    }
+
+    Ok(composite)
+}
@@ -1,9 +1,17 @@
 use crate::utils::*;
 use crate::buffer::*;
+use crate::state::*;
+use crate::clip::*;
+use crate::hiz::*;
 
 use glam::Vec3Swizzles;
 
+
 struct Edge {
+    start_x: real,
+    x_dist: real,
+    y_dist: real,
+
     curr_x: real,
     x_step: real,
 
@@ -18,17 +26,47 @@ impl Edge {
         let y_dist = max_y_vert.y - min_y_vert.y;
         let x_dist = max_y_vert.x - min_y_vert.x;
 
+        // Flat edges are never walked, but keep their steps finite anyway
+        let (x_step, ratio_step) = if y_dist > 0.0 {
+            (x_dist / y_dist, 1.0 / y_dist)
+        } else {
+            (0.0, 0.0)
+        };
+
         Edge {
+            start_x: min_y_vert.x,
+            x_dist,
+            y_dist,
+
             curr_x: min_y_vert.x,
-            x_step: x_dist / y_dist,
+            x_step,
 
             curr_ratio: 0.0,
-            ratio_step: 1.0 / y_dist,
+            ratio_step,
 
             first: true
         }
     }
 
+    // Rows are counted in `real`, vertices far outside the buffer are more rows away than an
+    // i32 holds
+    pub fn skip(&mut self, rows: real) {
+        if rows > 0.0 {
+            self.curr_x += self.x_step * rows;
+            self.curr_ratio += self.ratio_step * rows;
+        }
+    }
+
+    // Same as calling `next` after skipping `rows`, computed directly instead of accumulated
+    pub fn at(&self, rows: real) -> (real, real) {
+        if self.y_dist > 0.0 {
+            let ratio = rows / self.y_dist;
+            (self.start_x + self.x_dist * ratio, ratio)
+        } else {
+            (self.start_x, 0.0)
+        }
+    }
+
     pub fn next(&mut self) -> (real, real) {
         if self.first {
             self.first = false;
@@ -44,119 +82,1093 @@ fn lerp(start: Vec3, stop: Vec3, t: real) -> Vec3 {
     (1.0 - t) * start + t * stop
 }
 
+// Number of horizontally adjacent pixels shaded together by `Shader::fragment_wide`.
+// Interpolation, depth tests and writes run on all lanes at once. Shading does too for shaders
+// overriding `fragment_wide` with math on the lanes' Vec4s, SIMD backed with the f32 feature.
+// `examples/wide.rs` compares both paths.
+pub const LANES: usize = 4;
+
+pub fn lane_weights(weights: &[Vec4; 3], lane: usize) -> Vec3 {
+    Vec3::new(weights[0][lane], weights[1][lane], weights[2][lane])
+}
+
+// Interpolates a scalar attribute for every lane at once
+pub fn interpolate_lanes(p0: real, p1: real, p2: real, weights: &[Vec4; 3]) -> Vec4 {
+    weights[0] * p0 + weights[1] * p1 + weights[2] * p2
+}
+
 pub trait Shader {
     type Vertex;
     type VertexShaderOut: Interpolate;
 
+    // Shaders that compute their own depth (impostors, ray-marched objects...)
+    // set this and override `fragment_depth`. Early depth testing is skipped for them.
+    const WRITES_DEPTH: bool = false;
+
+    // How many of the distances returned by `vertex_clip` are used, up to `MAX_CLIP_DISTANCES`
+    const CLIP_DISTANCES: usize = 0;
+
+    // Shaders needing screen space derivatives of their varyings (e.g. to pick mip levels) set
+    // this and override `fragment_grad`, `fragment_depth_grad` if they write depth, and
+    // `fragment_wide_grad` for the wide path
+    const DERIVATIVES: bool = false;
+
     fn vertex(&self, vertex: &Self::Vertex) -> (Vec4, Self::VertexShaderOut);
+
+    // `vertex` with user clip distances alongside the position. Parts of primitives where any
+    // of them is negative are clipped away before rasterization.
+    fn vertex_clip(&self, vertex: &Self::Vertex) -> (Vec4, Self::VertexShaderOut, ClipDistances) {
+        let (position, varyings) = self.vertex(vertex);
+        (position, varyings, [0.0; MAX_CLIP_DISTANCES])
+    }
+
     fn fragment(&self, varyings: &Self::VertexShaderOut) -> Vec4;
 
-    fn draw(&self, buffer: &mut Buffer, vertices: &[Self::Vertex], indices: &[usize]) {
-        for triangle_indices in indices.chunks_exact(3) {
-            let (p0, varyings0) = self.vertex(&vertices[triangle_indices[0]]);
-            let (p1, varyings1) = self.vertex(&vertices[triangle_indices[1]]);
-            let (p2, varyings2) = self.vertex(&vertices[triangle_indices[2]]);
-
-            // let p0 = Vec3::new(p0[0], p0[1], p0[2]) * p0[3];
-            // let p1 = Vec3::new(p1[0], p1[1], p1[2]) * p1[3];
-            // let p2 = Vec3::new(p2[0], p2[1], p2[2]) * p2[3];
-
-            let p0_unsorted = p0;
-            let p1_unsorted = p1;
-            let p2_unsorted = p2;
-
-            fn screen_to_buffer_space(p: Vec4, w: usize, h: usize) -> Vec3 {
-                Vec3::new(
-                    p.x.remap(-1.0, 1.0, -0.5, w as real + 0.5).ceil(),
-                    p.y.remap(1.0, -1.0, -0.5, h as real + 0.5).ceil(),
-                    p.z
-                )
+    // `ddx` and `ddy` are how much the varyings change from one pixel to the next along x and y
+    fn fragment_grad(&self, varyings: &Self::VertexShaderOut, _ddx: &Self::VertexShaderOut, _ddy: &Self::VertexShaderOut) -> Vec4 {
+        self.fragment(varyings)
+    }
+
+    fn fragment_depth(&self, varyings: &Self::VertexShaderOut, depth: real) -> (Vec4, real) {
+        (self.fragment(varyings), depth)
+    }
+
+    fn fragment_depth_grad(
+        &self,
+        varyings: &Self::VertexShaderOut,
+        _ddx: &Self::VertexShaderOut,
+        _ddy: &Self::VertexShaderOut,
+        depth: real
+    ) -> (Vec4, real) {
+        self.fragment_depth(varyings, depth)
+    }
+
+    // Used when `DrawState::wide` is set. `weights` holds the barycentric weights of each
+    // lane, one Vec4 per triangle vertex. Override it to shade the lanes with vector math,
+    // by default every active lane goes through `fragment` on its own.
+    fn fragment_wide(&self, varyings: [&Self::VertexShaderOut; 3], weights: &[Vec4; 3], mask: [bool; LANES]) -> [Vec4; LANES] {
+        let mut colors = [Vec4::ZERO; LANES];
+
+        for (lane, color) in colors.iter_mut().enumerate() {
+            if mask[lane] {
+                let weights = lane_weights(weights, lane);
+                *color = self.fragment(&Self::VertexShaderOut::interpolate(varyings[0], varyings[1], varyings[2], &weights));
             }
+        }
+
+        colors
+    }
+
+    fn fragment_wide_grad(
+        &self,
+        varyings: [&Self::VertexShaderOut; 3],
+        weights: &[Vec4; 3],
+        mask: [bool; LANES],
+        ddx: &Self::VertexShaderOut,
+        ddy: &Self::VertexShaderOut
+    ) -> [Vec4; LANES] {
+        let mut colors = [Vec4::ZERO; LANES];
+
+        for (lane, color) in colors.iter_mut().enumerate() {
+            if mask[lane] {
+                let weights = lane_weights(weights, lane);
+                let interpolated = Self::VertexShaderOut::interpolate(varyings[0], varyings[1], varyings[2], &weights);
+                *color = self.fragment_grad(&interpolated, ddx, ddy);
+            }
+        }
+
+        colors
+    }
+
+    // Errors without drawing anything if the buffer doesn't hold both color and depth
+    fn draw(&self, buffer: &mut Buffer, vertices: &[Self::Vertex], indices: &[usize]) -> Result<(), &'static str> {
+        self.draw_with(buffer, vertices, indices, &mut DrawState::default())
+    }
+
+    fn draw_with(&self, buffer: &mut Buffer, vertices: &[Self::Vertex], indices: &[usize], state: &mut DrawState) -> Result<(), &'static str> {
+        run_pipeline(self, Some(buffer), vertices, indices, state, None)
+    }
+
+    // Transform feedback: appends every primitive's post-vertex-shader outputs to `feedback`,
+    // three entries per triangle. Rasterization is skipped entirely when `buffer` is None.
+    // Captured primitives are only clipped by user clip distances, the guard band is left to
+    // rasterization.
+    fn draw_feedback(
+        &self,
+        buffer: Option<&mut Buffer>,
+        vertices: &[Self::Vertex],
+        indices: &[usize],
+        state: &mut DrawState,
+        feedback: &mut Vec<(Vec4, Self::VertexShaderOut)>
+    ) -> Result<(), &'static str> {
+        run_pipeline(self, buffer, vertices, indices, state, Some(feedback))
+    }
+}
+
+fn run_pipeline<S: Shader + ?Sized>(
+    shader: &S,
+    buffer: Option<&mut Buffer>,
+    vertices: &[S::Vertex],
+    indices: &[usize],
+    state: &mut DrawState,
+    mut feedback: Option<&mut Vec<(Vec4, S::VertexShaderOut)>>
+) -> Result<(), &'static str> {
+    let mut stats = Stats::default();
+    let mut buffer = buffer;
+
+    // The rasterizer writes color and depth, anything else can't be drawn into
+    if buffer.as_ref().is_some_and(|buffer| buffer.depth != 4) {
+        return Err("Buffer's depth isn't 4!");
+    }
+
+    let guard_band = state.clamped_guard_band();
+
+    if let Some(buffer) = buffer.as_deref_mut() {
+        if state.hiz {
+            buffer.update_hiz();
+        }
+    }
+
+    for triangle_indices in indices.chunks_exact(3) {
+        let (p0, varyings0, clip0) = shader.vertex_clip(&vertices[triangle_indices[0]]);
+        let (p1, varyings1, clip1) = shader.vertex_clip(&vertices[triangle_indices[1]]);
+        let (p2, varyings2, clip2) = shader.vertex_clip(&vertices[triangle_indices[2]]);
+        stats.vertices_shaded += 3;
+        stats.primitives_submitted += 1;
+
+        if !(p0.is_finite() && p1.is_finite() && p2.is_finite()) {
+            stats.primitives_degenerate += 1;
+            continue;
+        }
+
+        let positions = [p0, p1, p2];
+        let user_planes = clip_distance_planes([&clip0, &clip1, &clip2], S::CLIP_DISTANCES);
+        let user_clipped = user_planes.iter().any(|distances| distances.min_element() < 0.0);
+        let guard_band_clipped = buffer.is_some() && !inside_guard_band(&positions, guard_band);
+
+        if !(user_clipped || guard_band_clipped) {
+            emit(shader, buffer.as_deref_mut(), state, &mut stats, feedback.as_deref_mut(), [
+                (p0, varyings0),
+                (p1, varyings1),
+                (p2, varyings2),
+            ]);
+            continue;
+        }
+
+        let mut polygon = triangle_polygon(&positions);
+        if user_clipped {
+            polygon = clip_to_planes(polygon, user_planes.into_iter().map(ClipPlane::Distances));
+        }
+        if guard_band_clipped {
+            polygon = clip_to_planes(polygon, guard_band_planes(guard_band));
+        }
+
+        if polygon.is_empty() {
+            stats.primitives_culled += 1;
+            continue;
+        }
+        stats.primitives_clipped += 1;
+
+        for i in 1..polygon.len() - 1 {
+            let triangle = [polygon[0], polygon[i], polygon[i + 1]].map(|vertex| (
+                vertex.position,
+                S::VertexShaderOut::interpolate(&varyings0, &varyings1, &varyings2, &vertex.weights)
+            ));
+
+            emit(shader, buffer.as_deref_mut(), state, &mut stats, feedback.as_deref_mut(), triangle);
+        }
+    }
+
+    if let Some(query) = &mut state.occlusion_query {
+        query.samples_passed += stats.depth_passed;
+    }
+
+    if let Some(total) = &mut state.stats {
+        *total += stats;
+    }
+
+    Ok(())
+}
+
+fn emit<S: Shader + ?Sized>(
+    shader: &S,
+    buffer: Option<&mut Buffer>,
+    state: &DrawState,
+    stats: &mut Stats,
+    feedback: Option<&mut Vec<(Vec4, S::VertexShaderOut)>>,
+    triangle: [(Vec4, S::VertexShaderOut); 3]
+) {
+    let [(p0, varyings0), (p1, varyings1), (p2, varyings2)] = triangle;
 
-            let p0 = (screen_to_buffer_space(p0, buffer.width, buffer.height), 0);
-            let p1 = (screen_to_buffer_space(p1, buffer.width, buffer.height), 1);
-            let p2 = (screen_to_buffer_space(p2, buffer.width, buffer.height), 2);
-
-            // Sort the points
-            let (p0, p1) = if p0.0.y > p1.0.y { (p1, p0) } else { (p0, p1) };
-            let (p1, p2) = if p1.0.y > p2.0.y { (p2, p1) } else { (p1, p2) };
-            let (p0, p1) = if p0.0.y > p1.0.y { (p1, p0) } else { (p0, p1) };
-
-            //                 {      /|   }
-            // edge_up_half <- {     / |   }
-            //                 {    /  |   }
-            //                 {   /   |   } -> edge_full
-            //                   { `   |   }
-            //                   {  `  |   }
-            // edge_down_half <- {   ` |   }
-            //                   {    `|   }
-
-            let mut edge_full = Edge::new(p0.0.xy(), p2.0.xy());
-            let mut edge_up_half = Edge::new(p0.0.xy(), p1.0.xy());
-            let mut edge_down_half = Edge::new(p1.0.xy(), p2.0.xy());
-
-            for y in (p0.0.y as i32)..(p2.0.y as i32) {
-                let mut full = edge_full.next();
-                let mut half = if y < p1.0.y as i32 {
-                    edge_up_half.next()
-                } else {
-                    edge_down_half.next()
+    if let Some(buffer) = buffer {
+        rasterize(shader, buffer, state, stats, [p0, p1, p2], [&varyings0, &varyings1, &varyings2]);
+    }
+
+    if let Some(feedback) = feedback {
+        feedback.push((p0, varyings0));
+        feedback.push((p1, varyings1));
+        feedback.push((p2, varyings2));
+    }
+}
+
+fn rasterize<S: Shader + ?Sized>(
+    shader: &S,
+    buffer: &mut Buffer,
+    state: &DrawState,
+    stats: &mut Stats,
+    positions: [Vec4; 3],
+    varyings: [&S::VertexShaderOut; 3]
+) {
+    // let p0 = Vec3::new(p0[0], p0[1], p0[2]) * p0[3];
+    // let p1 = Vec3::new(p1[0], p1[1], p1[2]) * p1[3];
+    // let p2 = Vec3::new(p2[0], p2[1], p2[2]) * p2[3];
+
+    let [p0, p1, p2] = positions;
+    let mut setup = Setup {
+        varyings,
+        depths: Vec3::new(p0.z, p1.z, p2.z),
+        max_depth: to_f32(p0.z.max(p1.z).max(p2.z)),
+        coarse_rows: Default::default(),
+        weight_steps: (Vec3::ZERO, Vec3::ZERO),
+        derivatives: None,
+    };
+    let hiz = state.hiz && !S::WRITES_DEPTH;
+
+    if state.rasterization != Rasterization::Standard {
+        rasterize_conservative(shader, buffer, state, stats, &mut setup, positions, hiz);
+        return;
+    }
+
+    let p0 = (screen_to_buffer_space(p0, buffer.width, buffer.height), 0);
+    let p1 = (screen_to_buffer_space(p1, buffer.width, buffer.height), 1);
+    let p2 = (screen_to_buffer_space(p2, buffer.width, buffer.height), 2);
+
+    let min_x = p0.0.x.min(p1.0.x).min(p2.0.x);
+    let max_x = p0.0.x.max(p1.0.x).max(p2.0.x);
+    let min_y = p0.0.y.min(p1.0.y).min(p2.0.y);
+    let max_y = p0.0.y.max(p1.0.y).max(p2.0.y);
+    let (w, h) = (buffer.width as real, buffer.height as real);
+
+    if max_x <= 0.0 || min_x >= w || max_y <= 0.0 || min_y >= h {
+        stats.primitives_culled += 1;
+        return;
+    }
+
+    // Zero area once snapped to the pixel grid, nothing would be covered
+    if (p1.0 - p0.0).xy().perp_dot((p2.0 - p0.0).xy()) == 0.0 {
+        stats.primitives_degenerate += 1;
+        return;
+    }
+
+    if S::DERIVATIVES {
+        setup.derive([p0.0.xy(), p1.0.xy(), p2.0.xy()]);
+    }
+
+    if state.coarse_shading() {
+        setup.weight_steps = weight_derivatives([p0.0.xy(), p1.0.xy(), p2.0.xy()]);
+    }
+
+    if hiz && !buffer.hiz().rect_visible(
+        min_x.max(0.0) as usize,
+        min_y.max(0.0) as usize,
+        max_x.min(w) as usize,
+        max_y.min(h) as usize,
+        setup.max_depth
+    ) {
+        stats.primitives_occluded += 1;
+        return;
+    }
+
+    // Sort the points
+    let (p0, p1) = if p0.0.y > p1.0.y { (p1, p0) } else { (p0, p1) };
+    let (p1, p2) = if p1.0.y > p2.0.y { (p2, p1) } else { (p1, p2) };
+    let (p0, p1) = if p0.0.y > p1.0.y { (p1, p0) } else { (p0, p1) };
+
+    //                 {      /|   }
+    // edge_up_half <- {     / |   }
+    //                 {    /  |   }
+    //                 {   /   |   } -> edge_full
+    //                   { `   |   }
+    //                   {  `  |   }
+    // edge_down_half <- {   ` |   }
+    //                   {    `|   }
+
+    let mut edge_full = Edge::new(p0.0.xy(), p2.0.xy());
+    let mut edge_up_half = Edge::new(p0.0.xy(), p1.0.xy());
+    let mut edge_down_half = Edge::new(p1.0.xy(), p2.0.xy());
+
+    // Rows of the vertices, truncated like the row loop below counts them
+    let (y0, y1) = (p0.0.y.trunc(), p1.0.y.trunc());
+
+    // Start at the first row inside the buffer instead of walking the clipped-off ones
+    let y_start = (p0.0.y as i32).max(0);
+    let y_end = (p2.0.y as i32).min(buffer.height as i32);
+
+    edge_full.skip(y_start as real - y0);
+    if (y_start as real) < y1 {
+        edge_up_half.skip(y_start as real - y0);
+    } else {
+        edge_down_half.skip(y_start as real - y1);
+    }
+
+    for y in y_start..y_end {
+        let upper = (y as real) < y1;
+        let (mut full, mut half) = if state.deterministic {
+            let half = if upper {
+                edge_up_half.at(y as real - y0)
+            } else {
+                edge_down_half.at(y as real - y1)
+            };
+            (edge_full.at(y as real - y0), half)
+        } else {
+            let half = if upper {
+                edge_up_half.next()
+            } else {
+                edge_down_half.next()
+            };
+            (edge_full.next(), half)
+        };
+
+        full.0 = full.0.ceil();
+        half.0 = half.0.ceil();
+
+        let mut influences = [
+            (0.0, 0.0),
+            (0.0, 0.0),
+            (0.0, 0.0)
+        ];
+
+        influences[p0.1].0 = if upper { 1.0 - half.1 } else { 0.0 };
+        influences[p1.1].0 = if upper { half.1 } else { 1.0 - half.1 };
+        influences[p2.1].0 = if upper { 0.0 } else { half.1 };
+
+        influences[p0.1].1 = 1.0 - full.1;
+        influences[p1.1].1 = 0.0;
+        influences[p2.1].1 = full.1;
+
+        let (min, max) = if full.0 > half.0 {
+            (half.0, full.0)
+        } else {
+            influences[0] = (influences[0].1, influences[0].0);
+            influences[1] = (influences[1].1, influences[1].0);
+            influences[2] = (influences[2].1, influences[2].0);
+
+            (full.0, half.0)
+        };
+
+        let left_weights = Vec3::new(influences[0].0, influences[1].0, influences[2].0);
+        let right_weights = Vec3::new(influences[0].1, influences[1].1, influences[2].1);
+
+        if max <= min {
+            continue;
+        }
+
+        let x_start = (min as i32).max(0);
+        let x_end = (max as i32).min(buffer.width as i32);
+
+        let x_ratio_step = 1.0 / (max - min);
+        let x_ratio = if x_start as real > min {
+            (x_start as real - min) * x_ratio_step
+        } else {
+            0.0
+        };
+
+        let span = Span {
+            y: y as usize,
+            x_start,
+            x_end,
+            x_origin: min,
+            left_weights,
+            right_weights,
+            x_ratio,
+            x_ratio_step,
+        };
+
+        submit_span(shader, buffer, state, stats, &mut setup, &span, hiz);
+    }
+}
+
+// Edge functions evaluated per pixel on unsnapped positions. Weights are still taken at the
+// pixel's sample point, clamped to the triangle when it lies outside.
+fn rasterize_conservative<S: Shader + ?Sized>(
+    shader: &S,
+    buffer: &mut Buffer,
+    state: &DrawState,
+    stats: &mut Stats,
+    setup: &mut Setup<S::VertexShaderOut>,
+    positions: [Vec4; 3],
+    hiz: bool
+) {
+    let q = positions.map(|p| screen_to_buffer_space_exact(p, buffer.width, buffer.height).xy());
+
+    let area = (q[1] - q[0]).perp_dot(q[2] - q[0]);
+    if area == 0.0 || !area.is_finite() {
+        stats.primitives_degenerate += 1;
+        return;
+    }
+
+    // Edge i is opposite vertex i, its edge function over the area is that vertex's weight
+    let edges = [(q[1], q[2]), (q[2], q[0]), (q[0], q[1])];
+    let weights_at = |p: Vec2| Vec3::from(edges.map(|(a, b)| (b - a).perp_dot(p - a))) / area;
+    let (weights_dx, weights_dy) = weight_derivatives(q);
+
+    if S::DERIVATIVES {
+        setup.derive(q);
+    }
+
+    setup.weight_steps = (weights_dx, weights_dy);
+
+    // How much each weight can change between the sample point and the pixel's corners
+    let margins = (weights_dx.abs() + weights_dy.abs()) * 0.5;
+    let margins = if state.rasterization == Rasterization::Overestimate { margins } else { -margins };
+
+    let min = q[0].min(q[1]).min(q[2]);
+    let max = q[0].max(q[1]).max(q[2]);
+    let x0 = ((min.x - 0.5).ceil() as i32).max(0);
+    let y0 = ((min.y - 0.5).ceil() as i32).max(0);
+    let x1 = ((max.x + 0.5).floor() as i32).min(buffer.width as i32 - 1);
+    let y1 = ((max.y + 0.5).floor() as i32).min(buffer.height as i32 - 1);
+
+    if x1 < x0 || y1 < y0 {
+        stats.primitives_culled += 1;
+        return;
+    }
+
+    if hiz && !buffer.hiz().rect_visible(x0 as usize, y0 as usize, x1 as usize + 1, y1 as usize + 1, setup.max_depth) {
+        stats.primitives_occluded += 1;
+        return;
+    }
+
+    for y in y0..=y1 {
+        // Pixels sampled inside the triangle are interpolated linearly, gather them into runs
+        let mut run: Option<(i32, Vec3)> = None;
+
+        for x in x0..=x1 + 1 {
+            let weights = weights_at(Vec2::new(x as real, y as real));
+            let covered = x <= x1 && (weights + margins).cmpge(Vec3::ZERO).all();
+
+            if covered && weights.cmpge(Vec3::ZERO).all() {
+                run.get_or_insert((x, weights));
+                continue;
+            }
+
+            if let Some((start, left_weights)) = run.take() {
+                let span = Span {
+                    y: y as usize,
+                    x_start: start,
+                    x_end: x,
+                    x_origin: start as real,
+                    left_weights,
+                    right_weights: left_weights + weights_dx,
+                    x_ratio: 0.0,
+                    x_ratio_step: 1.0,
+                };
+                submit_span(shader, buffer, state, stats, setup, &span, hiz);
+            }
+
+            if covered {
+                let clamped = weights.max(Vec3::ZERO);
+                let clamped = clamped / clamped.dot(Vec3::ONE);
+                let span = Span {
+                    y: y as usize,
+                    x_start: x,
+                    x_end: x + 1,
+                    x_origin: x as real,
+                    left_weights: clamped,
+                    right_weights: clamped,
+                    x_ratio: 0.0,
+                    x_ratio_step: 0.0,
                 };
+                submit_span(shader, buffer, state, stats, setup, &span, hiz);
+            }
+        }
+    }
+}
 
-                if y >= buffer.height as i32 || y < 0 {
-                    continue;
-                }
+struct Setup<'a, V> {
+    varyings: [&'a V; 3],
+    depths: Vec3,
+    max_depth: f32,
+    // Colors of the coarse blocks in the row of blocks being shaded, one row per coarse rate
+    coarse_rows: [CoarseRow; 3],
+    // Change of the weights per pixel along x and y, to find the centre of coarse blocks
+    weight_steps: (Vec3, Vec3),
+    // Varyings' derivatives along x and y, for shaders asking for them
+    derivatives: Option<(V, V)>,
+}
+
+impl<'a, V: Interpolate> Setup<'a, V> {
+    fn derive(&mut self, screen: [Vec2; 3]) {
+        let (weights_dx, weights_dy) = weight_derivatives(screen);
+        let [v0, v1, v2] = self.varyings;
 
-                full.0 = full.0.ceil();
-                half.0 = half.0.ceil();
+        self.derivatives = Some((
+            V::interpolate(v0, v1, v2, &weights_dx),
+            V::interpolate(v0, v1, v2, &weights_dy)
+        ));
+    }
+
+    fn fragment<S: Shader<VertexShaderOut = V> + ?Sized>(&self, shader: &S, varyings: &V) -> Vec4 {
+        match &self.derivatives {
+            Some((ddx, ddy)) => shader.fragment_grad(varyings, ddx, ddy),
+            None => shader.fragment(varyings),
+        }
+    }
+
+    // Derivatives span the whole block, so mip levels match the rate it's shaded at
+    fn coarse_fragment<S: Shader<VertexShaderOut = V> + ?Sized>(&self, shader: &S, varyings: &V, size: (usize, usize)) -> Vec4 {
+        if self.derivatives.is_none() {
+            return shader.fragment(varyings);
+        }
+
+        let (weights_dx, weights_dy) = self.weight_steps;
+        let [v0, v1, v2] = self.varyings;
+        let ddx = V::interpolate(v0, v1, v2, &(weights_dx * size.0 as real));
+        let ddy = V::interpolate(v0, v1, v2, &(weights_dy * size.1 as real));
+
+        shader.fragment_grad(varyings, &ddx, &ddy)
+    }
+
+    fn fragment_depth<S: Shader<VertexShaderOut = V> + ?Sized>(&self, shader: &S, varyings: &V, depth: real) -> (Vec4, real) {
+        match &self.derivatives {
+            Some((ddx, ddy)) => shader.fragment_depth_grad(varyings, ddx, ddy, depth),
+            None => shader.fragment_depth(varyings, depth),
+        }
+    }
+}
+
+// Spans arrive row by row, so a row of blocks is done once a span starts below it
+#[derive(Default)]
+struct CoarseRow {
+    block_y: Option<usize>,
+    colors: Vec<Option<Vec4>>,
+}
+
+impl CoarseRow {
+    fn block(&mut self, block_x: usize, block_y: usize, blocks: usize) -> &mut Option<Vec4> {
+        if self.block_y != Some(block_y) {
+            self.block_y = Some(block_y);
+            self.colors.clear();
+            self.colors.resize(blocks, None);
+        }
+
+        &mut self.colors[block_x]
+    }
+}
 
-                let mut influences = [
-                    (0.0, 0.0),
-                    (0.0, 0.0),
-                    (0.0, 0.0)
-                ];
+// Change of the barycentric weights per pixel along x and y, constant over a triangle
+fn weight_derivatives(screen: [Vec2; 3]) -> (Vec3, Vec3) {
+    let [s0, s1, s2] = screen;
+    let area = (s1 - s0).perp_dot(s2 - s0);
 
-                influences[p0.1].0 = if y < p1.0.y as i32 { 1.0 - half.1 } else { 0.0 };
-                influences[p1.1].0 = if y < p1.0.y as i32 { half.1 } else { 1.0 - half.1 };
-                influences[p2.1].0 = if y < p1.0.y as i32 { 0.0 } else { half.1 };
+    (
+        Vec3::new(s1.y - s2.y, s2.y - s0.y, s0.y - s1.y) / area,
+        Vec3::new(s2.x - s1.x, s0.x - s2.x, s1.x - s0.x) / area
+    )
+}
 
-                influences[p0.1].1 = 1.0 - full.1;
-                influences[p1.1].1 = 0.0;
-                influences[p2.1].1 = full.1;
+#[derive(Clone, Copy)]
+struct Span {
+    y: usize,
+    x_start: i32,
+    x_end: i32,
+    // Pixel where the ratio between the left and right weights is 0, before clamping to the buffer
+    x_origin: real,
+    left_weights: Vec3,
+    right_weights: Vec3,
+    x_ratio: real,
+    x_ratio_step: real,
+}
+
+impl Span {
+    fn ratio_at(&self, x: i32) -> real {
+        (x as real - self.x_origin) * self.x_ratio_step
+    }
+}
 
-                let (min, max) = if full.0 > half.0 {
-                    (half.0, full.0)
-                } else {
-                    influences[0] = (influences[0].1, influences[0].0);
-                    influences[1] = (influences[1].1, influences[1].0);
-                    influences[2] = (influences[2].1, influences[2].0);
+fn submit_span<S: Shader + ?Sized>(
+    shader: &S,
+    buffer: &mut Buffer,
+    state: &DrawState,
+    stats: &mut Stats,
+    setup: &mut Setup<S::VertexShaderOut>,
+    span: &Span,
+    hiz: bool
+) {
+    if hiz {
+        shade_span_hiz(shader, buffer, state, stats, setup, span);
+    } else {
+        dispatch_span(shader, buffer, state, stats, setup, span);
+    }
+}
 
-                    (full.0, half.0)
+fn dispatch_span<S: Shader + ?Sized>(
+    shader: &S,
+    buffer: &mut Buffer,
+    state: &DrawState,
+    stats: &mut Stats,
+    setup: &mut Setup<S::VertexShaderOut>,
+    span: &Span
+) {
+    // Shaders writing their own depth can't be tested ahead of shading, so they stay scalar.
+    // So does coarse shading, lanes would mostly hit the same block.
+    if state.wide && !S::WRITES_DEPTH && !state.coarse_shading() {
+        shade_span_wide(shader, buffer, state, stats, setup, span);
+    } else {
+        shade_span(shader, buffer, state, stats, setup, span);
+    }
+}
+
+// Splits the span at hidden tiles and only shades the runs in between
+fn shade_span_hiz<S: Shader + ?Sized>(
+    shader: &S,
+    buffer: &mut Buffer,
+    state: &DrawState,
+    stats: &mut Stats,
+    setup: &mut Setup<S::VertexShaderOut>,
+    span: &Span
+) {
+    let tile_y = span.y / HIZ_TILE_SIZE;
+    let tile_size = HIZ_TILE_SIZE as i32;
+
+    let mut run_start = None;
+    let mut x = span.x_start;
+
+    while x < span.x_end {
+        let tile_end = ((x / tile_size + 1) * tile_size).min(span.x_end);
+        let visible = buffer.hiz().tile_visible(x as usize / HIZ_TILE_SIZE, tile_y, setup.max_depth);
+
+        if visible && run_start.is_none() {
+            run_start = Some(x);
+        }
+
+        if !visible || tile_end == span.x_end {
+            if let Some(start) = run_start.take() {
+                let end = if visible { tile_end } else { x };
+                let run = Span {
+                    x_start: start,
+                    x_end: end,
+                    x_ratio: span.x_ratio + (start - span.x_start) as real * span.x_ratio_step,
+                    ..*span
                 };
+                dispatch_span(shader, buffer, state, stats, setup, &run);
+            }
+        }
 
-                let left_weights = Vec3::new(influences[0].0, influences[1].0, influences[2].0);
-                let right_weights = Vec3::new(influences[0].1, influences[1].1, influences[2].1);
+        x = tile_end;
+    }
+}
+
+fn shade_span<S: Shader + ?Sized>(
+    shader: &S,
+    buffer: &mut Buffer,
+    state: &DrawState,
+    stats: &mut Stats,
+    setup: &mut Setup<S::VertexShaderOut>,
+    span: &Span
+) {
+    let y = span.y;
+    let mut x_ratio = span.x_ratio;
+
+    for x in span.x_start..span.x_end {
+        if state.deterministic {
+            x_ratio = span.ratio_at(x);
+        }
+
+        let weights = lerp(span.left_weights, span.right_weights, x_ratio);
+        x_ratio += span.x_ratio_step;
 
-                let mut x_ratio = 0.0;
-                let x_ratio_step = 1.0 / (max - min);
-                for x in (min as i32)..(max as i32) {
-                    let weights = lerp(left_weights, right_weights, x_ratio);
+        let x = x as usize;
+        let depth = setup.depths.dot(weights);
+        if !depth.is_finite() {
+            continue;
+        }
 
-                    let fragment_colors = self.fragment(&Self::VertexShaderOut::interpolate(&varyings0, &varyings1, &varyings2, &weights));
-                    let fragment = [
-                        fragment_colors.x as f32,
-                        fragment_colors.y as f32,
-                        fragment_colors.z as f32,
-                        real::interpolate(&p0_unsorted.z, &p1_unsorted.z, &p2_unsorted.z, &weights) as f32
-                    ];
+        if !S::WRITES_DEPTH {
+            let depth = to_f32(depth);
+            if !(buffer.depth_test(x, y, depth) && state.peel_test(x, y, depth)) {
+                continue;
+            }
+        }
 
-                    if !(x >= buffer.width as i32 || x < 0) {
-                        buffer.set_pixel(x as usize, y as usize, &fragment).unwrap();
-                    }
+        let [v0, v1, v2] = setup.varyings;
+        let rate = if S::WRITES_DEPTH { ShadingRate::Rate1x1 } else { state.shading_rate_at(x, y) };
 
-                    x_ratio += x_ratio_step;
+        let (color, depth) = if rate != ShadingRate::Rate1x1 {
+            // Shaded once at the block's centre, clamped to the triangle when it lies outside
+            let (width, height) = rate.size();
+            let (block_x, block_y) = (x / width, y / height);
+            let (index, blocks) = (rate as usize - 1, buffer.width.div_ceil(width));
+            let cached = *setup.coarse_rows[index].block(block_x, block_y, blocks);
+            let color = match cached {
+                Some(color) => color,
+                None => {
+                    let (weights_dx, weights_dy) = setup.weight_steps;
+                    let centre_x = (block_x * width) as real + (width - 1) as real * 0.5;
+                    let centre_y = (block_y * height) as real + (height - 1) as real * 0.5;
+                    let centre = weights
+                        + weights_dx * (centre_x - x as real)
+                        + weights_dy * (centre_y - y as real);
+                    let centre = centre.max(Vec3::ZERO);
+                    let centre = centre / centre.dot(Vec3::ONE);
+
+                    let varyings = S::VertexShaderOut::interpolate(v0, v1, v2, &centre);
+                    let color = setup.coarse_fragment(shader, &varyings, (width, height));
+                    stats.fragments_shaded += 1;
+                    *setup.coarse_rows[index].block(block_x, block_y, blocks) = Some(color);
+                    color
                 }
+            };
+            (color, depth)
+        } else {
+            let interpolated = S::VertexShaderOut::interpolate(v0, v1, v2, &weights);
+            stats.fragments_shaded += 1;
+
+            if S::WRITES_DEPTH {
+                setup.fragment_depth(shader, &interpolated, depth)
+            } else {
+                (setup.fragment(shader, &interpolated), depth)
             }
+        };
+
+        write_fragment(buffer, state, stats, x, y, color, depth);
+    }
+}
+
+fn shade_span_wide<S: Shader + ?Sized>(
+    shader: &S,
+    buffer: &mut Buffer,
+    state: &DrawState,
+    stats: &mut Stats,
+    setup: &mut Setup<S::VertexShaderOut>,
+    span: &Span
+) {
+    let y = span.y;
+    let (left, right) = (span.left_weights, span.right_weights);
+    let depths = setup.depths;
+
+    let mut x = span.x_start;
+    let mut x_ratio = span.x_ratio;
+
+    while x < span.x_end {
+        let count = ((span.x_end - x) as usize).min(LANES);
+
+        // Every step below is a plain loop over the lanes' arrays, the compiler turns them into
+        // vector instructions. Deterministic ratios are computed exactly like the scalar path's.
+        let mut t = [0.0; LANES];
+        for (lane, t) in t.iter_mut().enumerate() {
+            *t = if state.deterministic {
+                (x as real - span.x_origin + lane as real) * span.x_ratio_step
+            } else {
+                x_ratio + lane as real * span.x_ratio_step
+            };
         }
+
+        let mut weights = [[0.0; LANES]; 3];
+        for lane in 0..LANES {
+            weights[0][lane] = (1.0 - t[lane]) * left.x + t[lane] * right.x;
+            weights[1][lane] = (1.0 - t[lane]) * left.y + t[lane] * right.y;
+            weights[2][lane] = (1.0 - t[lane]) * left.z + t[lane] * right.z;
+        }
+
+        let mut depth = [0.0; LANES];
+        let mut depth_f32 = [0.0; LANES];
+        for lane in 0..LANES {
+            depth[lane] = depths.x * weights[0][lane] + depths.y * weights[1][lane] + depths.z * weights[2][lane];
+            depth_f32[lane] = to_f32(depth[lane]);
+        }
+
+        // Lanes past the end of the span read a stored depth nothing can pass
+        let stored = buffer.get_depths::<LANES>(x as usize, y, count);
+        let mut mask = [false; LANES];
+        for lane in 0..LANES {
+            mask[lane] = depth[lane].is_finite() && stored[lane] < depth_f32[lane];
+        }
+
+        if state.peel.is_some() {
+            for (lane, mask) in mask.iter_mut().enumerate() {
+                *mask = *mask && state.peel_test(x as usize + lane, y, depth_f32[lane]);
+            }
+        }
+
+        if mask.contains(&true) {
+            let weights = weights.map(Vec4::from);
+            let colors = match &setup.derivatives {
+                Some((ddx, ddy)) => shader.fragment_wide_grad(setup.varyings, &weights, mask, ddx, ddy),
+                None => shader.fragment_wide(setup.varyings, &weights, mask),
+            };
+
+            let passed = mask.iter().filter(|&&mask| mask).count() as u64;
+            stats.fragments_shaded += passed;
+            stats.depth_passed += passed;
+
+            if state.write {
+                write_lanes(buffer, state.transparent, x as usize, y, mask, &colors, &depth_f32);
+                stats.pixels_written += passed;
+            }
+        }
+
+        x += LANES as i32;
+        x_ratio += LANES as real * span.x_ratio_step;
+    }
+}
+
+// Lanes in `mask` already passed the depth and peel tests
+fn write_lanes(
+    buffer: &mut Buffer,
+    transparent: bool,
+    x: usize,
+    y: usize,
+    mask: [bool; LANES],
+    colors: &[Vec4; LANES],
+    depths: &[f32; LANES]
+) {
+    let mut pixels = [[0.0; 4]; LANES];
+    for lane in 0..LANES {
+        let color = colors[lane];
+        pixels[lane] = [to_f32(color.x), to_f32(color.y), to_f32(color.z), depths[lane]];
+    }
+
+    if transparent {
+        for lane in (0..LANES).filter(|&lane| mask[lane]) {
+            let color = [pixels[lane][0], pixels[lane][1], pixels[lane][2], to_f32(colors[lane].w)];
+            buffer.push_fragment(x + lane, y, color, depths[lane]);
+        }
+    } else {
+        buffer.set_pixels(x, y, &pixels, mask);
+    }
+}
+
+fn write_fragment(buffer: &mut Buffer, state: &DrawState, stats: &mut Stats, x: usize, y: usize, color: Vec4, depth: real) {
+    if !(depth.is_finite() && buffer.depth_test(x, y, to_f32(depth)) && state.peel_test(x, y, to_f32(depth))) {
+        return;
+    }
+    stats.depth_passed += 1;
+
+    if state.write && state.transparent {
+        let color = [to_f32(color.x), to_f32(color.y), to_f32(color.z), to_f32(color.w)];
+        buffer.push_fragment(x, y, color, to_f32(depth));
+        stats.pixels_written += 1;
+    } else if state.write {
+        let fragment = [
+            to_f32(color.x),
+            to_f32(color.y),
+            to_f32(color.z),
+            to_f32(depth)
+        ];
+
+        buffer.set_pixel(x, y, &fragment).unwrap();
+        stats.pixels_written += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Varyings are the clip space position
+    struct DepthGrad;
+
+    impl Shader for DepthGrad {
+        type Vertex = Vec2;
+        type VertexShaderOut = Vec2;
+
+        const WRITES_DEPTH: bool = true;
+        const DERIVATIVES: bool = true;
+
+        fn vertex(&self, vertex: &Vec2) -> (Vec4, Vec2) {
+            (vertex.extend(0.0).extend(1.0), *vertex)
+        }
+
+        fn fragment(&self, _: &Vec2) -> Vec4 {
+            Vec4::ZERO
+        }
+
+        fn fragment_depth_grad(&self, _: &Vec2, ddx: &Vec2, ddy: &Vec2, depth: real) -> (Vec4, real) {
+            (Vec4::new(ddx.x, ddy.y, ddx.y + ddy.x, 1.0), depth)
+        }
+    }
+
+    struct Passthrough;
+
+    impl Shader for Passthrough {
+        type Vertex = Vec4;
+        type VertexShaderOut = real;
+
+        fn vertex(&self, vertex: &Vec4) -> (Vec4, real) {
+            (*vertex, vertex.x)
+        }
+
+        fn fragment(&self, x: &real) -> Vec4 {
+            Vec4::new(*x, 0.0, 0.0, 1.0)
+        }
+    }
+
+    #[test]
+    fn feedback_ignores_the_guard_band() {
+        // Just past the right edge of the viewport
+        let quad = [
+            Vec4::new(0.5, -0.5, 0.0, 1.0),
+            Vec4::new(1.5, -0.5, 0.0, 1.0),
+            Vec4::new(0.5, 0.5, 0.0, 1.0),
+            Vec4::new(1.5, 0.5, 0.0, 1.0),
+        ];
+
+        for guard_band in [1.0, 1.2, 4.0] {
+            let mut state = DrawState { guard_band, ..Default::default() };
+            let mut feedback = Vec::new();
+            Passthrough.draw_feedback(None, &quad, &[0, 1, 2, 3, 2, 1], &mut state, &mut feedback).unwrap();
+
+            assert_eq!(feedback.len(), 6);
+            assert_eq!(feedback[1].0, quad[1]);
+        }
+    }
+
+    fn coverage(triangle: [Vec4; 3], state: &mut DrawState) -> usize {
+        let mut buffer = Buffer::new(64, 64, 4);
+        buffer.clear();
+        Passthrough.draw_with(&mut buffer, &triangle, &[0, 1, 2], state).unwrap();
+
+        buffer.data().chunks(4).filter(|pixel| pixel[3].is_finite()).count()
+    }
+
+    fn full_screen(size: real) -> [Vec4; 3] {
+        [
+            Vec4::new(-size, -size, 0.0, 1.0),
+            Vec4::new(size, -size, 0.0, 1.0),
+            Vec4::new(0.0, size, 0.0, 1.0),
+        ]
+    }
+
+    #[test]
+    fn huge_triangles() {
+        for size in [1e4, 1e8, 1e30] {
+            for guard_band in [1.0, 4.0, 1e7] {
+                let mut state = DrawState { guard_band, stats: Some(Stats::default()), ..Default::default() };
+
+                assert_eq!(coverage(full_screen(size), &mut state), 64 * 64, "{} {}", size, guard_band);
+                let stats = state.stats.unwrap();
+                assert_eq!((stats.primitives_clipped, stats.primitives_degenerate), (1, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn partly_offscreen_triangles() {
+        // Covers the three quarters of the buffer where x + 2y <= 1
+        let triangle = [
+            Vec4::new(-1.0, -1.0, 0.0, 1.0),
+            Vec4::new(3.0, -1.0, 0.0, 1.0),
+            Vec4::new(-1.0, 1.0, 0.0, 1.0),
+        ];
+        let unclipped = coverage(triangle, &mut DrawState::default());
+        assert!(unclipped.abs_diff(64 * 64 * 3 / 4) <= 64, "{}", unclipped);
+
+        let mut state = DrawState { guard_band: 1.0, stats: Some(Stats::default()), ..Default::default() };
+        // Vertices added by clipping are snapped to the pixel grid too, the cut edges can move by
+        // a pixel per row
+        let clipped = coverage(triangle, &mut state);
+        assert!(clipped.abs_diff(unclipped) <= 64, "{} {}", clipped, unclipped);
+        assert_eq!(state.stats.unwrap().primitives_clipped, 1);
+
+        // Stretched far past the guard band along the same edges
+        let far = [triangle[0], Vec4::new(1e9 - 1.0, -1.0, 0.0, 1.0), Vec4::new(-1.0, 1e9 - 1.0, 0.0, 1.0)];
+        let mut state = DrawState { guard_band: 1e7, ..Default::default() };
+        let far_coverage = coverage(far, &mut state);
+        assert!(far_coverage > 64 * 64 - 64, "{}", far_coverage);
+    }
+
+    #[test]
+    fn invalid_vertices() {
+        let valid = full_screen(1.0);
+        let rasterizations = [Rasterization::Standard, Rasterization::Overestimate, Rasterization::Underestimate];
+
+        for rasterization in rasterizations {
+            for value in [real::NAN, real::INFINITY, real::NEG_INFINITY] {
+                for component in 0..4 {
+                    let mut triangle = valid;
+                    triangle[1][component] = value;
+
+                    let mut state = DrawState { rasterization, stats: Some(Stats::default()), ..Default::default() };
+                    assert_eq!(coverage(triangle, &mut state), 0);
+                    let stats = state.stats.unwrap();
+                    assert_eq!((stats.primitives_degenerate, stats.fragments_shaded), (1, 0));
+                }
+            }
+
+            // Zero area
+            let line = [Vec4::new(0.0, -0.5, 0.0, 1.0), Vec4::new(0.0, 0.5, 0.0, 1.0), Vec4::new(0.0, 0.0, 0.0, 1.0)];
+            let mut state = DrawState { rasterization, stats: Some(Stats::default()), ..Default::default() };
+            assert_eq!(coverage(line, &mut state), 0);
+            assert_eq!(state.stats.unwrap().primitives_degenerate, 1);
+
+            for size in [1e30, -1e30] {
+                let mut state = DrawState { rasterization, stats: Some(Stats::default()), ..Default::default() };
+                // Pixels on the diagonal the clipped square is split along aren't entirely inside
+                // either half
+                let covered = if rasterization == Rasterization::Underestimate { 64 * 63 } else { 64 * 64 };
+                assert_eq!(coverage(full_screen(size), &mut state), covered);
+                assert_eq!(state.stats.unwrap().primitives_degenerate, 0);
+
+                let mut triangle = valid;
+                triangle[2] = Vec4::new(size, size, size, 1.0);
+                let mut state = DrawState { rasterization, stats: Some(Stats::default()), ..Default::default() };
+                coverage(triangle, &mut state);
+                assert_eq!(state.stats.unwrap().primitives_submitted, 1);
+            }
+        }
+    }
+
+    struct Grad;
+
+    impl Shader for Grad {
+        type Vertex = Vec2;
+        type VertexShaderOut = Vec2;
+
+        const DERIVATIVES: bool = true;
+
+        fn vertex(&self, vertex: &Vec2) -> (Vec4, Vec2) {
+            DepthGrad.vertex(vertex)
+        }
+
+        fn fragment(&self, _: &Vec2) -> Vec4 {
+            Vec4::ZERO
+        }
+
+        fn fragment_grad(&self, _: &Vec2, ddx: &Vec2, ddy: &Vec2) -> Vec4 {
+            Vec4::new(ddx.x, ddy.y, 0.0, 1.0)
+        }
+    }
+
+    #[test]
+    fn coarse_derivatives_span_the_block() {
+        let triangle = [Vec2::new(-1.0, -1.0), Vec2::new(3.0, -1.0), Vec2::new(-1.0, 3.0)];
+        let derivatives = |shading_rate| {
+            let mut buffer = Buffer::new(8, 8, 4);
+            buffer.clear();
+            let mut state = DrawState { shading_rate, ..Default::default() };
+            Grad.draw_with(&mut buffer, &triangle, &[0, 1, 2], &mut state).unwrap();
+
+            let pixel = buffer.get_pixel(4, 4);
+            Vec2::new(pixel[0] as real, pixel[1] as real)
+        };
+
+        let pixel = derivatives(ShadingRate::Rate1x1);
+        assert!(pixel.x > 0.0 && pixel.y < 0.0);
+        assert_eq!(derivatives(ShadingRate::Rate1x2), pixel * Vec2::new(1.0, 2.0));
+        assert_eq!(derivatives(ShadingRate::Rate2x2), pixel * 2.0);
+        assert_eq!(derivatives(ShadingRate::Rate4x4), pixel * 4.0);
+    }
+
+    #[test]
+    fn depth_writing_shaders_get_derivatives() {
+        let mut buffer = Buffer::new(8, 8, 4);
+        buffer.clear();
+        let triangle = [Vec2::new(-1.0, -1.0), Vec2::new(3.0, -1.0), Vec2::new(-1.0, 3.0)];
+        DepthGrad.draw(&mut buffer, &triangle, &[0, 1, 2]).unwrap();
+
+        let pixel = buffer.get_pixel(4, 4);
+        assert!(pixel[0] > 0.0 && pixel[1] < 0.0, "{:?}", pixel);
+        assert_eq!(pixel[2], 0.0);
     }
 }
@@ -0,0 +1,309 @@
+use crate::utils::*;
+use crate::buffer::*;
+use crate::shader::*;
+use crate::texture::*;
+
+// Light matrices swap near and far, so that depth grows towards the light and the depth test
+// keeps the closest occluder. Depth goes from 0 at the far plane to 1 at the near plane.
+
+fn light_up(direction: Vec3) -> Vec3 {
+    if direction.normalize().y.abs() > 0.99 { Vec3::Z } else { Vec3::Y }
+}
+
+// Orthographic view along `direction` covering the sphere around `center`
+pub fn directional_light_matrix(direction: Vec3, center: Vec3, radius: real) -> Mat4 {
+    let eye = center - direction.normalize() * radius;
+
+    Mat4::orthographic_rh(-radius, radius, -radius, radius, 2.0 * radius, 0.0)
+        * Mat4::look_at_rh(eye, center, light_up(direction))
+}
+
+// `cone_angle` is the full opening angle of the spot, in radians. Shadows spread out with the
+// cone, since positions are divided by w before they reach the map.
+pub fn spot_light_matrix(position: Vec3, direction: Vec3, cone_angle: real, near: real, far: real) -> Mat4 {
+    Mat4::perspective_rh(cone_angle, 1.0, far, near)
+        * Mat4::look_at_rh(position, position + direction, light_up(direction))
+}
+
+// The rasterizer doesn't divide by w, so light space positions go through this first. Points
+// behind the light come out as NaN and are skipped.
+pub fn perspective_divide(p: Vec4) -> Vec4 {
+    if p.w > 0.0 { p / p.w } else { Vec4::splat(real::NAN) }
+}
+
+// Constant bias plus more on surfaces seen at a grazing angle from the light
+pub fn slope_scaled_bias(normal: Vec3, to_light: Vec3, constant: real, slope: real) -> real {
+    let cos = normal.normalize().dot(to_light.normalize()).clamp(0.0, 1.0);
+    let tan = (1.0 - cos * cos).sqrt() / cos.max(0.1);
+
+    constant + slope * tan
+}
+
+struct DepthOnly {
+    matrix: Mat4,
+}
+
+impl Shader for DepthOnly {
+    type Vertex = Vec3;
+    type VertexShaderOut = real;
+
+    fn vertex(&self, vertex: &Vec3) -> (Vec4, real) {
+        (perspective_divide(self.matrix * vertex.extend(1.0)), 0.0)
+    }
+
+    fn fragment(&self, _: &real) -> Vec4 {
+        Vec4::ZERO
+    }
+}
+
+pub struct ShadowMap {
+    // Other shaders can render into it too, with `matrix` as their projection followed by
+    // `perspective_divide`
+    pub buffer: Buffer,
+    pub matrix: Mat4,
+}
+
+impl ShadowMap {
+    pub fn new(size: usize, matrix: Mat4) -> ShadowMap {
+        let mut buffer = Buffer::new(size, size, 4);
+        buffer.clear();
+
+        ShadowMap { buffer, matrix }
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    // Depth-only pass of triangles placed in the world by `model`
+    pub fn render(&mut self, positions: &[Vec3], indices: &[usize], model: Mat4) -> Result<(), &'static str> {
+        let shader = DepthOnly { matrix: self.matrix * model };
+
+        shader.draw(&mut self.buffer, positions, indices)
+    }
+
+    // Where a world position lands in the map, in pixels, and its depth as seen from the light.
+    // Uses the same mapping as the rasterizer.
+    pub fn project(&self, world: Vec3) -> Vec3 {
+        screen_to_buffer_space_exact(perspective_divide(self.matrix * world.extend(1.0)), self.buffer.width, self.buffer.height)
+    }
+
+    // 1 where the closest occluder stored at pixel (x, y) isn't in front of `depth`, lit outside the map
+    pub fn compare(&self, x: i64, y: i64, depth: real) -> real {
+        let (width, height) = (self.buffer.width as i64, self.buffer.height as i64);
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return 1.0;
+        }
+
+        if from_f32(self.buffer.get_depth(x as usize, y as usize)) > depth {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    // Hard shadows: 0 in shadow, 1 lit
+    pub fn visibility(&self, world: Vec3, bias: real) -> real {
+        let p = self.project(world);
+        if !p.is_finite() {
+            return 1.0;
+        }
+
+        self.compare(p.x.round() as i64, p.y.round() as i64, p.z + bias)
+    }
+
+    // Percentage-closer filtering: share of the (2 * radius + 1)² closest pixels that are lit
+    pub fn visibility_pcf(&self, world: Vec3, bias: real, radius: usize) -> real {
+        let p = self.project(world);
+        if !p.is_finite() {
+            return 1.0;
+        }
+
+        let (x, y) = (p.x.round() as i64, p.y.round() as i64);
+        let radius = radius as i64;
+        let mut lit = 0.0;
+
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                lit += self.compare(x + dx, y + dy, p.z + bias);
+            }
+        }
+
+        lit / ((2 * radius + 1) * (2 * radius + 1)) as real
+    }
+}
+
+// Depth and squared depth per pixel, blurred, for soft shadows from a single filtered lookup
+pub struct VarianceShadowMap {
+    pub moments: Texture,
+    pub matrix: Mat4,
+    pub sampler: Sampler,
+    // Floor for the variance, avoids acne on flat surfaces
+    pub min_variance: real,
+    // Cuts off the faint light leaking through overlapping occluders, between 0 and 1
+    pub light_bleeding_reduction: real,
+}
+
+impl VarianceShadowMap {
+    // Pixels nothing was drawn to count as the far plane (depth 0)
+    pub fn from_shadow_map(shadow_map: &ShadowMap, blur_radius: usize) -> Result<VarianceShadowMap, &'static str> {
+        let buffer = &shadow_map.buffer;
+        let (width, height) = (buffer.width, buffer.height);
+
+        let mut moments: Vec<[f32; 2]> = (0..width * height).map(|i| {
+            let depth = buffer.get_depth(i % width, i / width);
+            let depth = if depth.is_finite() { depth } else { 0.0 };
+            [depth, depth * depth]
+        }).collect();
+
+        if blur_radius > 0 {
+            moments = box_blur(&moments, width, height, blur_radius, true);
+            moments = box_blur(&moments, width, height, blur_radius, false);
+        }
+
+        let data = moments.iter().flat_map(|[m1, m2]| [*m1, *m2, 0.0, 1.0]).collect();
+
+        Ok(VarianceShadowMap {
+            moments: Texture::from_rgba32f(width, height, data)?,
+            matrix: shadow_map.matrix,
+            sampler: Sampler::new(Filter::Bilinear, Wrap::Clamp),
+            min_variance: 0.00002,
+            light_bleeding_reduction: 0.2,
+        })
+    }
+
+    // Upper bound of the lit fraction (Chebyshev's inequality), between 0 and 1
+    pub fn visibility(&self, world: Vec3) -> real {
+        let (width, height) = (self.moments.width, self.moments.height);
+        let p = screen_to_buffer_space_exact(perspective_divide(self.matrix * world.extend(1.0)), width, height);
+        if !p.is_finite() || p.x < -0.5 || p.y < -0.5 || p.x > width as real - 0.5 || p.y > height as real - 0.5 {
+            return 1.0;
+        }
+
+        // Pixel x is sampled at x, texel x is centered on (x + 0.5) / width
+        let uv = (p.truncate() + Vec2::splat(0.5)) / Vec2::new(width as real, height as real);
+        let moments = self.sampler.sample(&self.moments, uv);
+        let (mean, mean_squared) = (moments.x, moments.y);
+
+        if p.z >= mean {
+            return 1.0;
+        }
+
+        let variance = (mean_squared - mean * mean).max(self.min_variance);
+        let distance = mean - p.z;
+        let upper_bound = variance / (variance + distance * distance);
+
+        ((upper_bound - self.light_bleeding_reduction) / (1.0 - self.light_bleeding_reduction)).clamp(0.0, 1.0)
+    }
+}
+
+fn box_blur(values: &[[f32; 2]], width: usize, height: usize, radius: usize, horizontal: bool) -> Vec<[f32; 2]> {
+    let mut blurred = vec![[0.0; 2]; values.len()];
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0.0; 2];
+            let mut count = 0.0;
+
+            for offset in -(radius as i64)..=radius as i64 {
+                let (sx, sy) = if horizontal { (x as i64 + offset, y as i64) } else { (x as i64, y as i64 + offset) };
+                if sx < 0 || sy < 0 || sx >= width as i64 || sy >= height as i64 {
+                    continue;
+                }
+
+                let value = values[sy as usize * width + sx as usize];
+                sum[0] += value[0];
+                sum[1] += value[1];
+                count += 1.0;
+            }
+
+            blurred[y * width + x] = [sum[0] / count, sum[1] / count];
+        }
+    }
+
+    blurred
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Left half of a 4x4 map covered at depth 0.5
+    fn half_covered() -> ShadowMap {
+        let mut shadow_map = ShadowMap::new(4, Mat4::IDENTITY);
+        for y in 0..4 {
+            for x in 0..2 {
+                shadow_map.buffer.set_pixel(x, y, &[0.0, 0.0, 0.0, 0.5]).unwrap();
+            }
+        }
+
+        shadow_map
+    }
+
+    #[test]
+    fn compare() {
+        let shadow_map = half_covered();
+
+        assert_eq!(shadow_map.compare(1, 1, 0.4), 0.0);
+        assert_eq!(shadow_map.compare(1, 1, 0.5), 1.0);
+        assert_eq!(shadow_map.compare(1, 1, 0.6), 1.0);
+        assert_eq!(shadow_map.compare(2, 1, 0.0), 1.0);
+        assert_eq!(shadow_map.compare(-1, 1, 0.0), 1.0);
+        assert_eq!(shadow_map.compare(1, 4, 0.0), 1.0);
+    }
+
+    #[test]
+    fn pcf() {
+        let shadow_map = half_covered();
+
+        // Pixel 2 is sampled at 0 in normalized device coordinates, pixel 1 at -0.4
+        assert_eq!(shadow_map.visibility(Vec3::ZERO, 0.0), 1.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(-0.4, 0.0, 0.0), 0.0), 0.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(-0.4, 0.0, 0.0), 0.5), 1.0);
+
+        assert_eq!(shadow_map.visibility_pcf(Vec3::ZERO, 0.0, 0), 1.0);
+        assert!((shadow_map.visibility_pcf(Vec3::ZERO, 0.0, 1) - 6.0 / 9.0).abs() < 1e-6);
+        // Pixels outside the map count as lit
+        assert!((shadow_map.visibility_pcf(Vec3::new(-0.4, 0.0, 0.0), 0.0, 1) - 3.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rendered_occluder() {
+        let matrix = directional_light_matrix(-Vec3::Y, Vec3::ZERO, 2.0);
+        let mut shadow_map = ShadowMap::new(32, matrix);
+        let quad = [
+            Vec3::new(-0.5, 1.0, -0.5),
+            Vec3::new(0.5, 1.0, -0.5),
+            Vec3::new(-0.5, 1.0, 0.5),
+            Vec3::new(0.5, 1.0, 0.5),
+        ];
+        shadow_map.render(&quad, &[0, 1, 2, 3, 2, 1], Mat4::IDENTITY).unwrap();
+
+        assert_eq!(shadow_map.visibility(Vec3::ZERO, 0.001), 0.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(0.0, 1.5, 0.0), 0.001), 1.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(1.5, 0.0, 0.0), 0.001), 1.0);
+        assert_eq!(shadow_map.visibility_pcf(Vec3::ZERO, 0.001, 2), 0.0);
+    }
+
+    #[test]
+    fn spot_shadows_grow_with_distance() {
+        let matrix = spot_light_matrix(Vec3::new(0.0, 2.0, 0.0), -Vec3::Y, 1.5, 0.1, 10.0);
+        let mut shadow_map = ShadowMap::new(128, matrix);
+        let quad = [
+            Vec3::new(-0.25, 1.0, -0.25),
+            Vec3::new(0.25, 1.0, -0.25),
+            Vec3::new(-0.25, 1.0, 0.25),
+            Vec3::new(0.25, 1.0, 0.25),
+        ];
+        shadow_map.render(&quad, &[0, 1, 2, 3, 2, 1], Mat4::IDENTITY).unwrap();
+
+        // The occluder is 1 away from the light, so its shadow is twice as wide 2 away and three
+        // times as wide 3 away
+        assert_eq!(shadow_map.visibility(Vec3::new(0.4, 0.0, 0.0), 0.0), 0.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(0.6, 0.0, 0.0), 0.0), 1.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(0.65, -1.0, 0.0), 0.0), 0.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(0.85, -1.0, 0.0), 0.0), 1.0);
+        // Behind the light
+        assert_eq!(shadow_map.visibility(Vec3::new(0.0, 3.0, 0.0), 0.0), 1.0);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::utils::*;
+use crate::buffer::*;
+
+use std::ops::AddAssign;
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Stats {
+    pub vertices_shaded: u64,
+    pub primitives_submitted: u64,
+    // Entirely outside the buffer, the guard band or a user clip plane
+    pub primitives_culled: u64,
+    // Crossing the guard band or a user clip plane, split into smaller triangles
+    pub primitives_clipped: u64,
+    // NaN or infinite positions, or no area left to rasterize
+    pub primitives_degenerate: u64,
+    // Rejected by the hierarchical Z buffer, hidden behind earlier draws
+    pub primitives_occluded: u64,
+    pub fragments_shaded: u64,
+    pub depth_passed: u64,
+    pub pixels_written: u64,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.vertices_shaded += other.vertices_shaded;
+        self.primitives_submitted += other.primitives_submitted;
+        self.primitives_culled += other.primitives_culled;
+        self.primitives_clipped += other.primitives_clipped;
+        self.primitives_degenerate += other.primitives_degenerate;
+        self.primitives_occluded += other.primitives_occluded;
+        self.fragments_shaded += other.fragments_shaded;
+        self.depth_passed += other.depth_passed;
+        self.pixels_written += other.pixels_written;
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct OcclusionQuery {
+    pub samples_passed: u64,
+}
+
+impl OcclusionQuery {
+    pub fn any_samples_passed(&self) -> bool {
+        self.samples_passed > 0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rasterization {
+    // Pixels whose sample point is inside the triangle
+    Standard,
+    // Every pixel the triangle touches at all, thin triangles never vanish
+    Overestimate,
+    // Only pixels entirely inside the triangle
+    Underestimate,
+}
+
+// Pixels sharing one `Shader::fragment` call, ordered from finest to coarsest
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum ShadingRate {
+    Rate1x1,
+    Rate1x2,
+    Rate2x2,
+    Rate4x4,
+}
+
+impl ShadingRate {
+    // Width and height of a block in pixels
+    pub fn size(self) -> (usize, usize) {
+        match self {
+            ShadingRate::Rate1x1 => (1, 1),
+            ShadingRate::Rate1x2 => (1, 2),
+            ShadingRate::Rate2x2 => (2, 2),
+            ShadingRate::Rate4x4 => (4, 4),
+        }
+    }
+}
+
+// One shading rate per screen tile
+pub struct ShadingRateImage {
+    rates: Vec<ShadingRate>,
+    pub tile_size: usize,
+    // In tiles
+    pub width: usize,
+    pub height: usize,
+}
+
+impl ShadingRateImage {
+    // `width` and `height` are the buffer's, in pixels
+    pub fn new(width: usize, height: usize, tile_size: usize) -> ShadingRateImage {
+        let tile_size = tile_size.max(1);
+        let (width, height) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+
+        ShadingRateImage {
+            rates: vec![ShadingRate::Rate1x1; width * height],
+            tile_size, width, height
+        }
+    }
+
+    pub fn set_rate(&mut self, tile_x: usize, tile_y: usize, rate: ShadingRate) -> Result<(), &'static str> {
+        if tile_x >= self.width || tile_y >= self.height {
+            Err("Tile is outside the shading rate image!")
+        } else {
+            self.rates[tile_y * self.width + tile_x] = rate;
+            Ok(())
+        }
+    }
+
+    // Rate of the tile containing pixel (x, y), full rate outside the image
+    pub fn get_rate(&self, x: usize, y: usize) -> ShadingRate {
+        let (tile_x, tile_y) = (x / self.tile_size, y / self.tile_size);
+
+        if tile_x < self.width && tile_y < self.height {
+            self.rates[tile_y * self.width + tile_x]
+        } else {
+            ShadingRate::Rate1x1
+        }
+    }
+}
+
+// Largest guard band drawing uses, past it the rasterizer's pixel coordinates would run out of
+// precision on large buffers
+pub const MAX_GUARD_BAND: real = 1024.0;
+
+pub struct DrawState {
+    pub stats: Option<Stats>,
+    pub occlusion_query: Option<OcclusionQuery>,
+    // When false, fragments are depth tested and counted but nothing is written,
+    // e.g. for drawing bounding boxes with an occlusion query.
+    pub write: bool,
+    // Triangles reaching further than this (in normalized device coordinates) are clipped
+    // geometrically. Anything in between only gets its spans clamped to the buffer.
+    // Kept between 1 and `MAX_GUARD_BAND`.
+    pub guard_band: real,
+    // Shade `LANES` pixels per call through `Shader::fragment_wide`, see `LANES` for when it helps
+    pub wide: bool,
+    // Reject triangles and tiles hidden according to the buffer's depth pyramid.
+    // Never applies to shaders writing their own depth.
+    pub hiz: bool,
+    pub rasterization: Rasterization,
+    // Coverage and depth stay per pixel, only colors are shared within a block. Where a rate
+    // image is set the coarser of both rates applies. Ignored by shaders writing depth.
+    pub shading_rate: ShadingRate,
+    pub shading_rate_image: Option<ShadingRateImage>,
+    // Fragments passing the depth test are appended to the buffer's fragment lists instead of
+    // being written, composited in depth order by `Buffer::resolve_fragments`.
+    pub transparent: bool,
+    // Depth peeling: only fragments behind this layer's depth are drawn, see `peel::depth_peel`
+    pub peel: Option<Buffer>,
+    // Interpolation weights are computed from each pixel's position instead of being accumulated
+    // along edges and spans, so output only depends on the inputs. Identical whether spans are
+    // split by the depth pyramid or shaded wide.
+    pub deterministic: bool,
+}
+
+impl Default for DrawState {
+    fn default() -> DrawState {
+        DrawState {
+            stats: None,
+            occlusion_query: None,
+            write: true,
+            guard_band: 4.0,
+            wide: false,
+            hiz: false,
+            rasterization: Rasterization::Standard,
+            shading_rate: ShadingRate::Rate1x1,
+            shading_rate_image: None,
+            transparent: false,
+            peel: None,
+            deterministic: false,
+        }
+    }
+}
+
+impl DrawState {
+    // NaN falls back to the viewport
+    pub fn clamped_guard_band(&self) -> real {
+        if self.guard_band.is_nan() {
+            1.0
+        } else {
+            self.guard_band.clamp(1.0, MAX_GUARD_BAND)
+        }
+    }
+
+    pub fn coarse_shading(&self) -> bool {
+        self.shading_rate != ShadingRate::Rate1x1 || self.shading_rate_image.is_some()
+    }
+
+    pub fn peel_test(&self, x: usize, y: usize, depth: f32) -> bool {
+        match &self.peel {
+            Some(layer) => x < layer.width && y < layer.height && depth < layer.get_depth(x, y),
+            None => true,
+        }
+    }
+
+    pub fn shading_rate_at(&self, x: usize, y: usize) -> ShadingRate {
+        match &self.shading_rate_image {
+            Some(image) => self.shading_rate.max(image.get_rate(x, y)),
+            None => self.shading_rate,
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use crate::utils::*;
+use crate::texture::*;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// Output of `generate_tangents`. Vertices get split where their triangles disagree on the
+// handedness of the texture mapping or aren't connected through shared edges, so each output
+// vertex copies the attributes of input vertex `vertices[i]`.
+pub struct Tangents {
+    pub vertices: Vec<usize>,
+    pub tangents: Vec<Vec4>,
+    pub indices: Vec<usize>,
+}
+
+impl Tangents {
+    // Input attribute laid out like the output vertices
+    pub fn remap<T: Clone>(&self, attribute: &[T]) -> Vec<T> {
+        self.vertices.iter().map(|&vertex| attribute[vertex].clone()).collect()
+    }
+}
+
+// Per-vertex tangents following MikkTSpace: face tangents are projected onto the vertex normal's
+// plane and weighted by the corner angle, vertices are split like MikkTSpace splits them, and w
+// holds the bitangent's sign so that bitangent = w * normal.cross(tangent).
+pub fn generate_tangents(positions: &[Vec3], normals: &[Vec3], uvs: &[Vec2], indices: &[usize]) -> Result<Tangents, &'static str> {
+    if normals.len() != positions.len() || uvs.len() != positions.len() {
+        return Err("Vertex attributes have different lengths!");
+    }
+
+    if indices.iter().any(|&index| index >= positions.len()) {
+        return Err("Index is out of range!");
+    }
+
+    // Like MikkTSpace, vertices with the same position, normal and uv are one vertex when looking
+    // for shared edges
+    let welded = weld(positions, normals, uvs);
+    // Trailing indices are ignored, like when drawing
+    let indices = &indices[..indices.len() - indices.len() % 3];
+    let faces = indices.len() / 3;
+
+    // Tangent along growing u and whether the mapping keeps its handedness, None for faces
+    // without a texture mapping to follow or without area
+    let face_tangents: Vec<Option<(Vec3, bool)>> = indices.chunks_exact(3).map(|triangle| {
+        let [i0, i1, i2] = [triangle[0], triangle[1], triangle[2]];
+        if welded[i0] == welded[i1] || welded[i1] == welded[i2] || welded[i2] == welded[i0] {
+            return None;
+        }
+
+        let (edge1, edge2) = (positions[i1] - positions[i0], positions[i2] - positions[i0]);
+        let (delta1, delta2) = (uvs[i1] - uvs[i0], uvs[i2] - uvs[i0]);
+
+        let det = delta1.perp_dot(delta2);
+        let tangent = ((edge1 * delta2.y - edge2 * delta1.y) / det).normalize_or_zero();
+        if det == 0.0 || !det.is_finite() || tangent == Vec3::ZERO {
+            return None;
+        }
+
+        Some((tangent, det > 0.0))
+    }).collect();
+
+    // Corners of a vertex are grouped when their faces share an edge and agree on the handedness
+    let mut groups: Vec<usize> = (0..indices.len()).collect();
+    let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+
+    for face in (0..faces).filter(|&face| face_tangents[face].is_some()) {
+        for corner in 0..3 {
+            let a = welded[indices[face * 3 + corner]];
+            let b = welded[indices[face * 3 + (corner + 1) % 3]];
+            edges.entry((a, b)).or_default().push(face);
+        }
+    }
+
+    let corner_of = |face: usize, vertex: usize| (0..3).map(|corner| face * 3 + corner).find(|&corner| welded[indices[corner]] == vertex);
+
+    for (&(a, b), faces) in &edges {
+        let neighbors = match edges.get(&(b, a)) {
+            Some(neighbors) => neighbors,
+            None => continue,
+        };
+
+        for &face in faces {
+            for &neighbor in neighbors.iter().filter(|&&neighbor| face_tangents[neighbor].map(|t| t.1) == face_tangents[face].map(|t| t.1)) {
+                for vertex in [a, b] {
+                    if let (Some(c0), Some(c1)) = (corner_of(face, vertex), corner_of(neighbor, vertex)) {
+                        union(&mut groups, c0, c1);
+                    }
+                }
+            }
+        }
+    }
+
+    // Corners of faces without a tangent join any group of their vertex
+    let mut any_group: Vec<Option<usize>> = vec![None; positions.len()];
+    for corner in (0..indices.len()).filter(|&corner| face_tangents[corner / 3].is_some()) {
+        any_group[welded[indices[corner]]].get_or_insert(corner);
+    }
+
+    for corner in (0..indices.len()).filter(|&corner| face_tangents[corner / 3].is_none()) {
+        if let Some(other) = any_group[welded[indices[corner]]] {
+            union(&mut groups, corner, other);
+        }
+    }
+
+    // Sum of the face tangents in the vertex normal's plane, weighted by the corner angle, and the
+    // handedness of each group
+    let mut sums = vec![(Vec3::ZERO, true); indices.len()];
+
+    for corner in 0..indices.len() {
+        let (face_tangent, preserves) = match face_tangents[corner / 3] {
+            Some(face_tangent) => face_tangent,
+            None => continue,
+        };
+
+        let (face, k) = (corner / 3 * 3, corner % 3);
+        let vertex = indices[corner];
+        let previous = indices[face + (k + 2) % 3];
+        let next = indices[face + (k + 1) % 3];
+
+        let normal = normals[vertex].normalize_or_zero();
+        let project = |v: Vec3| (v - normal * normal.dot(v)).normalize_or_zero();
+        let cos = project(positions[next] - positions[vertex]).dot(project(positions[previous] - positions[vertex]));
+        let angle = cos.clamp(-1.0, 1.0).acos();
+
+        let group = find(&mut groups, corner);
+        sums[group] = (sums[group].0 + project(face_tangent) * angle, preserves);
+    }
+
+    // The corners of an input vertex become one vertex per group, the first keeps its index
+    let mut vertices: Vec<usize> = (0..positions.len()).collect();
+    let mut tangents: Vec<Vec4> = normals.iter().map(|&normal| perpendicular(normal).extend(1.0)).collect();
+    let mut kept = vec![false; positions.len()];
+    let mut outputs: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut output_indices = Vec::with_capacity(indices.len());
+
+    for (corner, &vertex) in indices.iter().enumerate() {
+        let group = find(&mut groups, corner);
+        let output = *outputs.entry((vertex, group)).or_insert_with(|| {
+            if !kept[vertex] {
+                kept[vertex] = true;
+                vertex
+            } else {
+                vertices.push(vertex);
+                tangents.push(Vec4::ZERO);
+                vertices.len() - 1
+            }
+        });
+
+        let (sum, preserves) = sums[group];
+        let tangent = sum.normalize_or_zero();
+        let tangent = if tangent == Vec3::ZERO { perpendicular(normals[vertex]) } else { tangent };
+        tangents[output] = tangent.extend(if preserves { 1.0 } else { -1.0 });
+        output_indices.push(output);
+    }
+
+    Ok(Tangents { vertices, tangents, indices: output_indices })
+}
+
+// Index of the first vertex with the same position, normal and uv as each vertex
+fn weld(positions: &[Vec3], normals: &[Vec3], uvs: &[Vec2]) -> Vec<usize> {
+    let key = |i: usize| [positions[i].x, positions[i].y, positions[i].z, normals[i].x, normals[i].y, normals[i].z, uvs[i].x, uvs[i].y];
+    let compare = |a: usize, b: usize| {
+        key(a).iter().zip(key(b).iter()).map(|(x, y)| x.total_cmp(y)).find(|order| order.is_ne()).unwrap_or(Ordering::Equal)
+    };
+
+    let mut order: Vec<usize> = (0..positions.len()).collect();
+    order.sort_by(|&a, &b| compare(a, b).then(a.cmp(&b)));
+
+    let mut welded: Vec<usize> = (0..positions.len()).collect();
+    for pair in order.windows(2) {
+        if compare(pair[0], pair[1]).is_eq() {
+            welded[pair[1]] = welded[pair[0]];
+        }
+    }
+
+    welded
+}
+
+fn find(groups: &mut [usize], mut i: usize) -> usize {
+    while groups[i] != i {
+        groups[i] = groups[groups[i]];
+        i = groups[i];
+    }
+
+    i
+}
+
+fn union(groups: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(groups, a), find(groups, b));
+    groups[a.max(b)] = a.min(b);
+}
+
+// Any unit vector perpendicular to `normal`
+fn perpendicular(normal: Vec3) -> Vec3 {
+    let axis = if normal.x.abs() < 0.9 { Vec3::X } else { Vec3::Y };
+    normal.cross(axis).normalize_or_zero()
+}
+
+// Texel of a normal map to a tangent space direction. Green is expected to grow along v, which
+// goes down the image; maps authored with green pointing up the image (OpenGL style) need
+// `flip_green`.
+pub fn decode_normal(texel: Vec4, flip_green: bool) -> Vec3 {
+    let normal = texel.truncate() * 2.0 - Vec3::ONE;
+
+    if flip_green {
+        Vec3::new(normal.x, -normal.y, normal.z)
+    } else {
+        normal
+    }
+}
+
+// Tangent space direction to the space of the interpolated normal and tangent. Like MikkTSpace,
+// the interpolated vectors are used without normalizing them first.
+pub fn tangent_to_normal(tangent_space: Vec3, normal: Vec3, tangent: Vec4) -> Vec3 {
+    let bitangent = normal.cross(tangent.truncate()) * tangent.w;
+
+    (tangent.truncate() * tangent_space.x + bitangent * tangent_space.y + normal * tangent_space.z).normalize_or_zero()
+}
+
+pub fn sample_normal_map(texture: &Texture, sampler: &Sampler, uv: Vec2, normal: Vec3, tangent: Vec4) -> Vec3 {
+    tangent_to_normal(decode_normal(sampler.sample(texture, uv), false), normal, tangent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_tangent(tangent: Vec4, expected: Vec4) {
+        assert!((tangent - expected).length() < 1e-5, "{:?} != {:?}", tangent, expected);
+    }
+
+    #[test]
+    fn mirrored_uvs_split_the_seam() {
+        // Strip along x with u = |x|, the middle column is on the mirror seam
+        let positions: Vec<Vec3> = [-1.0, 1.0].iter().flat_map(|&y| [-1.0, 0.0, 1.0].map(|x| Vec3::new(x, -y, 0.0))).collect();
+        let uvs: Vec<Vec2> = positions.iter().map(|p| Vec2::new(p.x.abs(), (1.0 - p.y) / 2.0)).collect();
+        let normals = [Vec3::Z; 6];
+        let indices = [0, 3, 1, 4, 1, 3, 1, 4, 2, 5, 2, 4];
+
+        let result = generate_tangents(&positions, &normals, &uvs, &indices).unwrap();
+
+        assert_eq!(result.vertices, [0, 1, 2, 3, 4, 5, 1, 4]);
+        assert_eq!(result.indices, [0, 3, 1, 4, 1, 3, 6, 7, 2, 5, 2, 7]);
+        for vertex in [0, 1, 3, 4] {
+            assert_tangent(result.tangents[vertex], Vec4::new(-1.0, 0.0, 0.0, 1.0));
+        }
+        for vertex in [2, 5, 6, 7] {
+            assert_tangent(result.tangents[vertex], Vec4::new(1.0, 0.0, 0.0, -1.0));
+        }
+        assert_eq!(result.remap(&uvs)[6], uvs[1]);
+    }
+
+    #[test]
+    fn corners_are_weighted_by_angle() {
+        // Corners of 90° with tangent x and 45° with tangent (x + y) / √2 around the origin. The
+        // second face has its own copies of the shared vertices, welded back for the grouping.
+        let positions = [
+            Vec3::ZERO, Vec3::X, Vec3::Y,
+            Vec3::ZERO, Vec3::Y, Vec3::new(-1.0, 1.0, 0.0),
+        ];
+        let uvs = [
+            Vec2::ZERO, Vec2::new(1.0, 0.0), Vec2::new(0.5, -1.0),
+            Vec2::ZERO, Vec2::new(0.5, -1.0), Vec2::new(0.0, -2.0),
+        ];
+        let normals = [Vec3::Z; 6];
+
+        let result = generate_tangents(&positions, &normals, &uvs, &[0, 1, 2, 3, 4, 5]).unwrap();
+
+        // (π / 2 * x + π / 4 * (x + y) / √2), normalized
+        let expected = Vec4::new(0.967_538, 0.252_734, 0.0, -1.0);
+        assert_eq!(result.vertices.len(), 6);
+        assert_tangent(result.tangents[0], expected);
+        assert_tangent(result.tangents[3], expected);
+        assert_tangent(result.tangents[1], Vec4::new(1.0, 0.0, 0.0, -1.0));
+        assert_tangent(result.tangents[5], Vec4::new(0.5_f64.sqrt() as real, 0.5_f64.sqrt() as real, 0.0, -1.0));
+    }
+
+    #[test]
+    fn tangents_are_in_the_normal_plane() {
+        let positions = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let uvs = [Vec2::ZERO, Vec2::new(1.0, 0.0), Vec2::new(0.0, -1.0)];
+        let normals = [Vec3::new(1.0, 0.0, 1.0).normalize(); 3];
+
+        let result = generate_tangents(&positions, &normals, &uvs, &[0, 1, 2]).unwrap();
+
+        for tangent in result.tangents {
+            assert_tangent(tangent, Vec4::new(0.5_f64.sqrt() as real, 0.0, -0.5_f64.sqrt() as real, -1.0));
+        }
+    }
+}
@@ -0,0 +1,493 @@
+use crate::utils::*;
+use crate::buffer::*;
+
+use std::io::BufRead;
+
+// Which part of a `Buffer` becomes a texture
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferAttachment {
+    // Opaque colors, black where nothing was drawn
+    Color,
+    // Depth in every color channel. Where nothing was drawn it's the farthest depth that was,
+    // so filtering doesn't turn infinities into NaN.
+    Depth,
+}
+
+enum TextureData {
+    Unorm8(Vec<u8>),
+    Float(Vec<f32>),
+}
+
+// RGBA texels, row by row from the top. Texture coordinates go from (0, 0) at the top left
+// to (1, 1) at the bottom right.
+pub struct Texture {
+    data: TextureData,
+    // Levels 1 and up, see `generate_mipmaps`
+    mips: Vec<Texture>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Texture {
+    pub fn from_rgba8(width: usize, height: usize, data: Vec<u8>) -> Result<Texture, &'static str> {
+        if data.len() != width * height * 4 {
+            Err("Texture data doesn't match its size!")
+        } else {
+            Ok(Texture { data: TextureData::Unorm8(data), mips: Vec::new(), width, height })
+        }
+    }
+
+    pub fn from_rgba32f(width: usize, height: usize, data: Vec<f32>) -> Result<Texture, &'static str> {
+        if data.len() != width * height * 4 {
+            Err("Texture data doesn't match its size!")
+        } else {
+            Ok(Texture { data: TextureData::Float(data), mips: Vec::new(), width, height })
+        }
+    }
+
+    // Float texture with every texel computed from the texture coordinates of its center,
+    // e.g. to bake procedural patterns
+    pub fn from_fn(width: usize, height: usize, texel: impl Fn(Vec2) -> Vec4) -> Texture {
+        let size = Vec2::new(width as real, height as real);
+        let data = (0..width * height)
+            .flat_map(|i| texel((Vec2::new((i % width) as real, (i / width) as real) + Vec2::splat(0.5)) / size)
+                .to_array()
+                .map(to_f32))
+            .collect();
+
+        Texture { data: TextureData::Float(data), mips: Vec::new(), width, height }
+    }
+
+    // 8 bits per channel, values are divided by 255 when sampled
+    pub fn from_image(image: &image::DynamicImage) -> Texture {
+        let image = image.to_rgba8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+
+        Texture { data: TextureData::Unorm8(image.into_raw()), mips: Vec::new(), width, height }
+    }
+
+    // Float storage with colors decoded from sRGB to linear, alpha is kept linear as is
+    pub fn from_image_float(image: &image::DynamicImage) -> Texture {
+        let image = image.to_rgba8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let data = image.into_raw().chunks_exact(4)
+            .flat_map(|texel| [
+                srgb_to_linear(texel[0]),
+                srgb_to_linear(texel[1]),
+                srgb_to_linear(texel[2]),
+                texel[3] as f32 / 255.0
+            ])
+            .collect();
+
+        Texture { data: TextureData::Float(data), mips: Vec::new(), width, height }
+    }
+
+    // Halves the size down to 1x1, every texel averaging the 2x2 texels above it
+    pub fn generate_mipmaps(&mut self) {
+        self.mips.clear();
+
+        let mut level = self.downsample();
+        while let Some(next) = level {
+            level = next.downsample();
+            self.mips.push(next);
+        }
+    }
+
+    // Precomputed levels 1 and up, each half the size of the one before
+    pub fn set_mips(&mut self, mips: Vec<Texture>) -> Result<(), &'static str> {
+        let (mut width, mut height) = (self.width, self.height);
+
+        for mip in mips.iter() {
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+            if mip.width != width || mip.height != height {
+                return Err("Mip levels must halve the size each time!");
+            }
+        }
+
+        self.mips = mips;
+        Ok(())
+    }
+
+    pub fn levels(&self) -> usize {
+        1 + self.mips.len()
+    }
+
+    // Level 0 is the texture itself, past the last level the smallest one
+    pub fn level(&self, level: usize) -> &Texture {
+        match level {
+            0 => self,
+            _ => self.mips.get(level - 1).or(self.mips.last()).unwrap_or(self),
+        }
+    }
+
+    pub(crate) fn downsample(&self) -> Option<Texture> {
+        if self.width == 0 || self.height == 0 || (self.width == 1 && self.height == 1) {
+            return None;
+        }
+
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, y0) = (x * 2, y * 2);
+                let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+
+                texels.push((self.texel(x0, y0) + self.texel(x1, y0) + self.texel(x0, y1) + self.texel(x1, y1)) * 0.25);
+            }
+        }
+
+        Some(self.with_texels(width, height, texels))
+    }
+
+    // Texel by texel mean of two textures of the same size, in this one's format
+    pub(crate) fn average(&self, other: &Texture) -> Texture {
+        let texels = (0..self.width * self.height)
+            .map(|i| (self.texel(i % self.width, i / self.width) + other.texel(i % self.width, i / self.width)) * 0.5)
+            .collect();
+
+        self.with_texels(self.width, self.height, texels)
+    }
+
+    // New texture stored the same way as this one
+    fn with_texels(&self, width: usize, height: usize, texels: Vec<Vec4>) -> Texture {
+        let data = match self.data {
+            TextureData::Unorm8(_) => TextureData::Unorm8(texels.iter()
+                .flat_map(|texel| texel.to_array())
+                .map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8)
+                .collect()),
+            TextureData::Float(_) => TextureData::Float(texels.iter()
+                .flat_map(|texel| texel.to_array())
+                .map(to_f32)
+                .collect()),
+        };
+
+        Texture { data, mips: Vec::new(), width, height }
+    }
+
+    // Copy of a rectangle of texels, without mipmaps
+    pub fn region(&self, x: usize, y: usize, width: usize, height: usize) -> Result<Texture, &'static str> {
+        if x + width > self.width || y + height > self.height {
+            Err("Region is outside the texture!")
+        } else {
+            Ok(self.copy_texels(width, height, |column, row| (x + column, y + row)))
+        }
+    }
+
+    pub fn rotated_180(&self) -> Texture {
+        let (width, height) = (self.width, self.height);
+        self.copy_texels(width, height, |x, y| (width - 1 - x, height - 1 - y))
+    }
+
+    // New texture of the given size, each texel copied from the one at `source(x, y)`
+    fn copy_texels(&self, width: usize, height: usize, source: impl Fn(usize, usize) -> (usize, usize)) -> Texture {
+        let indices = (0..width * height).flat_map(|i| {
+            let (x, y) = source(i % width, i / width);
+            let start = (y * self.width + x) * 4;
+            start..start + 4
+        });
+
+        let data = match &self.data {
+            TextureData::Unorm8(data) => TextureData::Unorm8(indices.map(|i| data[i]).collect()),
+            TextureData::Float(data) => TextureData::Float(indices.map(|i| data[i]).collect()),
+        };
+
+        Texture { data, mips: Vec::new(), width, height }
+    }
+
+    // Radiance .hdr images, linear float storage. OpenEXR isn't supported by the image crate
+    // this is built with.
+    pub fn from_hdr(reader: impl BufRead) -> Result<Texture, &'static str> {
+        let decoder = image::codecs::hdr::HdrDecoder::new(reader).map_err(|_| "Not a Radiance HDR image!")?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr().map_err(|_| "Couldn't decode the HDR image!")?;
+
+        let data = pixels.iter().flat_map(|pixel| [pixel.0[0], pixel.0[1], pixel.0[2], 1.0]).collect();
+        Texture::from_rgba32f(metadata.width as usize, metadata.height as usize, data)
+    }
+
+    pub fn open_hdr(path: impl AsRef<std::path::Path>) -> Result<Texture, &'static str> {
+        let file = std::fs::File::open(path).map_err(|_| "Couldn't open the HDR image!")?;
+        Texture::from_hdr(std::io::BufReader::new(file))
+    }
+
+    // Snapshot of a rendered buffer in float storage, to sample it in later passes
+    pub fn from_buffer(buffer: &Buffer, attachment: BufferAttachment) -> Result<Texture, &'static str> {
+        let required = match attachment {
+            BufferAttachment::Color => 3,
+            BufferAttachment::Depth => 4,
+        };
+        if buffer.depth < required {
+            return Err("Buffer doesn't hold that attachment!");
+        }
+
+        let far = match attachment {
+            BufferAttachment::Color => 0.0,
+            BufferAttachment::Depth => buffer.data().chunks_exact(buffer.depth)
+                .map(|pixel| pixel[3])
+                .filter(|depth| depth.is_finite())
+                .reduce(f32::min)
+                .unwrap_or(f32::MIN),
+        };
+
+        let data = buffer.data().chunks_exact(buffer.depth).flat_map(|pixel| match attachment {
+            BufferAttachment::Color => {
+                let channel = |value: f32| if value.is_finite() { value } else { 0.0 };
+                [channel(pixel[0]), channel(pixel[1]), channel(pixel[2]), 1.0]
+            }
+            BufferAttachment::Depth => {
+                let depth = if pixel[3].is_finite() { pixel[3] } else { far };
+                [depth, depth, depth, 1.0]
+            }
+        }).collect();
+
+        Texture::from_rgba32f(buffer.width, buffer.height, data)
+    }
+
+    pub fn texel(&self, x: usize, y: usize) -> Vec4 {
+        let start = (y * self.width + x) * 4;
+
+        match &self.data {
+            TextureData::Unorm8(data) => Vec4::new(
+                data[start] as real,
+                data[start + 1] as real,
+                data[start + 2] as real,
+                data[start + 3] as real
+            ) / 255.0,
+            TextureData::Float(data) => Vec4::new(
+                from_f32(data[start]),
+                from_f32(data[start + 1]),
+                from_f32(data[start + 2]),
+                from_f32(data[start + 3])
+            ),
+        }
+    }
+}
+
+fn srgb_to_linear(channel: u8) -> f32 {
+    let value = channel as f32 / 255.0;
+
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+// How mip levels are picked for a level of detail
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MipFilter {
+    // Always the full resolution texture
+    None,
+    Nearest,
+    // Blends the two closest levels, trilinear filtering along with `Filter::Bilinear`
+    Linear,
+}
+
+impl MipFilter {
+    // Shared by every kind of texture, `sample_level` samples one of its `levels`
+    pub(crate) fn sample(self, lod: real, levels: usize, sample_level: impl Fn(usize) -> Vec4) -> Vec4 {
+        let lod = lod.max(0.0).min((levels - 1) as real);
+
+        match self {
+            MipFilter::None => sample_level(0),
+            MipFilter::Nearest => sample_level(lod.round() as usize),
+            MipFilter::Linear => {
+                let level = lod.floor();
+                let finer = sample_level(level as usize);
+                if lod == level {
+                    return finer;
+                }
+
+                finer.lerp(sample_level(level as usize + 1), lod - level)
+            }
+        }
+    }
+}
+
+// What happens to texture coordinates outside [0, 1]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+    // Sampler::border is used instead of any texel
+    Border,
+}
+
+impl Wrap {
+    // Index of the texel to read along an axis of `size` texels, None for the border
+    pub(crate) fn apply(self, coord: i64, size: usize) -> Option<usize> {
+        let size = size as i64;
+
+        match self {
+            Wrap::Repeat => Some(coord.rem_euclid(size) as usize),
+            Wrap::Clamp => Some(coord.clamp(0, size - 1) as usize),
+            Wrap::Mirror => {
+                let coord = coord.rem_euclid(2 * size);
+                Some(if coord < size { coord } else { 2 * size - 1 - coord } as usize)
+            }
+            Wrap::Border => (0..size).contains(&coord).then_some(coord as usize),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub wrap_u: Wrap,
+    pub wrap_v: Wrap,
+    // Along the third axis of volume textures
+    pub wrap_w: Wrap,
+    pub border: Vec4,
+    pub mip_filter: MipFilter,
+    pub lod_bias: real,
+    // Most samples taken along the longer axis of a stretched footprint, 1 turns it off
+    pub max_anisotropy: usize,
+}
+
+impl Default for Sampler {
+    fn default() -> Sampler {
+        Sampler {
+            filter: Filter::Bilinear,
+            wrap_u: Wrap::Repeat,
+            wrap_v: Wrap::Repeat,
+            wrap_w: Wrap::Repeat,
+            border: Vec4::ZERO,
+            mip_filter: MipFilter::Linear,
+            lod_bias: 0.0,
+            max_anisotropy: 1,
+        }
+    }
+}
+
+impl Sampler {
+    pub fn new(filter: Filter, wrap: Wrap) -> Sampler {
+        Sampler { filter, wrap_u: wrap, wrap_v: wrap, wrap_w: wrap, ..Default::default() }
+    }
+
+    // `ddx` and `ddy` are the screen space derivatives of `uv`, as given to `Shader::fragment_grad`
+    pub fn sample_grad(&self, texture: &Texture, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        if !uv.is_finite() {
+            return self.non_finite(&[self.wrap_u, self.wrap_v]);
+        }
+
+        let size = Vec2::new(texture.width as real, texture.height as real);
+        let (length_x, length_y) = ((ddx * size).length(), (ddy * size).length());
+        let (major, minor, major_axis) = if length_x > length_y {
+            (length_x, length_y, ddx)
+        } else {
+            (length_y, length_x, ddy)
+        };
+
+        let samples = if self.max_anisotropy > 1 && minor > 0.0 {
+            ((major / minor).ceil() as usize).clamp(1, self.max_anisotropy)
+        } else {
+            1
+        };
+
+        // Each sample covers its share of the footprint's length
+        let lod = (major / samples as real).log2() + self.lod_bias;
+        if samples == 1 {
+            return self.sample_lod(texture, uv, lod);
+        }
+
+        let mut color = Vec4::ZERO;
+        for i in 0..samples {
+            let offset = (i as real + 0.5) / samples as real - 0.5;
+            color += self.sample_lod(texture, uv + major_axis * offset, lod);
+        }
+
+        color / samples as real
+    }
+
+    // `lod_bias` isn't added, like for every texture it only applies to the level of detail
+    // `sample_grad` computes
+    pub fn sample_lod(&self, texture: &Texture, uv: Vec2, lod: real) -> Vec4 {
+        self.mip_filter.sample(lod, texture.levels(), |level| self.sample(texture.level(level), uv))
+    }
+
+    // Full resolution only
+    pub fn sample(&self, texture: &Texture, uv: Vec2) -> Vec4 {
+        if texture.width == 0 || texture.height == 0 {
+            return self.border;
+        }
+
+        if !uv.is_finite() {
+            return self.non_finite(&[self.wrap_u, self.wrap_v]);
+        }
+
+        let position = uv * Vec2::new(texture.width as real, texture.height as real);
+
+        match self.filter {
+            Filter::Nearest => self.fetch(texture, position.x.floor() as i64, position.y.floor() as i64),
+            Filter::Bilinear => {
+                // Texel centers sit at half coordinates
+                let position = position - Vec2::splat(0.5);
+                let corner = position.floor();
+                let t = position - corner;
+                let (x, y) = (corner.x as i64, corner.y as i64);
+                let (x1, y1) = (x.saturating_add(1), y.saturating_add(1));
+
+                let top = self.fetch(texture, x, y).lerp(self.fetch(texture, x1, y), t.x);
+                let bottom = self.fetch(texture, x, y1).lerp(self.fetch(texture, x1, y1), t.x);
+                top.lerp(bottom, t.y)
+            }
+        }
+    }
+
+    // Result for NaN or infinite coordinates along axes wrapped by `wraps`, no wrap mode can
+    // bring them back to a texel
+    pub(crate) fn non_finite(&self, wraps: &[Wrap]) -> Vec4 {
+        if wraps.contains(&Wrap::Border) {
+            self.border
+        } else {
+            Vec4::ZERO
+        }
+    }
+
+    // Texel at integer coordinates, after wrapping
+    pub fn fetch(&self, texture: &Texture, x: i64, y: i64) -> Vec4 {
+        match (self.wrap_u.apply(x, texture.width), self.wrap_v.apply(y, texture.height)) {
+            (Some(x), Some(y)) => texture.texel(x, y),
+            _ => self.border,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_finite_coordinates() {
+        let texture = Texture::from_rgba8(2, 2, vec![255; 16]).unwrap();
+        let border = Vec4::new(0.25, 0.5, 0.75, 1.0);
+
+        for uv in [Vec2::new(real::NAN, 0.5), Vec2::new(0.5, real::INFINITY), Vec2::splat(real::NEG_INFINITY)] {
+            for filter in [Filter::Nearest, Filter::Bilinear] {
+                let sampler = Sampler { border, ..Sampler::new(filter, Wrap::Border) };
+                assert_eq!(sampler.sample(&texture, uv), border);
+                assert_eq!(sampler.sample_grad(&texture, uv, Vec2::splat(0.5), Vec2::ZERO), border);
+
+                for wrap in [Wrap::Repeat, Wrap::Clamp, Wrap::Mirror] {
+                    let sampler = Sampler { border, ..Sampler::new(filter, wrap) };
+                    assert_eq!(sampler.sample(&texture, uv), Vec4::ZERO);
+                    assert_eq!(sampler.sample_lod(&texture, uv, 0.5), Vec4::ZERO);
+                }
+            }
+        }
+
+        let sampler = Sampler::new(Filter::Bilinear, Wrap::Repeat);
+        let color = sampler.sample_grad(&texture, Vec2::splat(0.5), Vec2::splat(real::NAN), Vec2::ZERO);
+        assert!(color.is_finite());
+    }
+}
@@ -1,10 +1,94 @@
-#[allow(non_camel_case_types)]
-pub type real = f64;
-pub type Vec2 = glam::DVec2;
-pub type Vec3 = glam::DVec3;
-pub type Vec4 = glam::DVec4;
-pub type Quat = glam::DQuat;
-pub type Mat4 = glam::DMat4;
+#[cfg(not(feature = "f32"))]
+mod precision {
+    #[allow(non_camel_case_types)]
+    pub type real = f64;
+    pub type Vec2 = glam::DVec2;
+    pub type Vec3 = glam::DVec3;
+    pub type Vec4 = glam::DVec4;
+    pub type Quat = glam::DQuat;
+    pub type Mat4 = glam::DMat4;
+    pub use std::f64::consts;
+
+    // Buffers always store f32
+    pub fn to_f32(value: real) -> f32 {
+        value as f32
+    }
+
+    pub fn from_f32(value: f32) -> real {
+        value as real
+    }
+}
+
+#[cfg(feature = "f32")]
+mod precision {
+    #[allow(non_camel_case_types)]
+    pub type real = f32;
+    pub type Vec2 = glam::Vec2;
+    pub type Vec3 = glam::Vec3;
+    pub type Vec4 = glam::Vec4;
+    pub type Quat = glam::Quat;
+    pub type Mat4 = glam::Mat4;
+    pub use std::f32::consts;
+
+    pub fn to_f32(value: real) -> f32 {
+        value
+    }
+
+    pub fn from_f32(value: f32) -> real {
+        value
+    }
+}
+
+pub use precision::*;
+
+// Normalized device coordinates to pixels, snapped to the pixel grid
+pub fn screen_to_buffer_space(p: Vec4, w: usize, h: usize) -> Vec3 {
+    let p = screen_to_buffer_space_exact(p, w, h);
+    Vec3::new(p.x.ceil(), p.y.ceil(), p.z)
+}
+
+// Same mapping without the snapping. Pixel (x, y) is sampled at (x, y) and covers the square
+// half a pixel around it.
+pub fn screen_to_buffer_space_exact(p: Vec4, w: usize, h: usize) -> Vec3 {
+    Vec3::new(
+        p.x.remap(-1.0, 1.0, -0.5, w as real + 0.5),
+        p.y.remap(1.0, -1.0, -0.5, h as real + 0.5),
+        p.z
+    )
+}
+
+// Inverse of `screen_to_buffer_space` for a pixel's sample point
+pub fn buffer_to_screen_space(x: usize, y: usize, w: usize, h: usize) -> Vec2 {
+    Vec2::new(
+        (x as real).remap(-0.5, w as real + 0.5, -1.0, 1.0),
+        (y as real).remap(-0.5, h as real + 0.5, 1.0, -1.0)
+    )
+}
+
+// Screen space bounds (pixels, depth) of a box projected by `matrix`, None if any corner
+// ends up NaN or infinite
+pub fn project_aabb(matrix: Mat4, min: Vec3, max: Vec3, w: usize, h: usize) -> Option<(Vec3, Vec3)> {
+    let mut screen_min = Vec3::splat(real::INFINITY);
+    let mut screen_max = Vec3::splat(real::NEG_INFINITY);
+
+    for i in 0..8 {
+        let corner = Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z }
+        );
+
+        let p = screen_to_buffer_space(matrix * corner.extend(1.0), w, h);
+        if !p.is_finite() {
+            return None;
+        }
+
+        screen_min = screen_min.min(p);
+        screen_max = screen_max.max(p);
+    }
+
+    Some((screen_min, screen_max))
+}
 
 pub trait Remap {
     fn remap(self, a: Self, b: Self, new_a: Self, new_b: Self) -> Self;
@@ -0,0 +1,190 @@
+use crate::utils::*;
+use crate::texture::*;
+
+// 3D texture made of `depth` slices, texture coordinate w going from the first slice to the last.
+// Sampled with `Filter::Bilinear` it's filtered linearly along all three axes.
+pub struct VolumeTexture {
+    slices: Vec<Texture>,
+    // Levels 1 and up, halving the depth along with the slices' size
+    mips: Vec<VolumeTexture>,
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+}
+
+impl VolumeTexture {
+    pub fn from_slices(slices: Vec<Texture>) -> Result<VolumeTexture, &'static str> {
+        let (width, height) = match slices.first() {
+            Some(slice) => (slice.width, slice.height),
+            None => return Err("Volume texture needs at least one slice!"),
+        };
+
+        if slices.iter().any(|slice| slice.width != width || slice.height != height) {
+            Err("Volume texture slices must be of the same size!")
+        } else {
+            Ok(VolumeTexture { depth: slices.len(), slices, mips: Vec::new(), width, height })
+        }
+    }
+
+    // RGBA texels, row by row then slice by slice
+    pub fn from_rgba8(width: usize, height: usize, depth: usize, data: Vec<u8>) -> Result<VolumeTexture, &'static str> {
+        if data.len() != width * height * depth * 4 {
+            return Err("Texture data doesn't match its size!");
+        }
+
+        let slice_size = (width * height * 4).max(1);
+        VolumeTexture::from_slices(data.chunks(slice_size)
+            .map(|slice| Texture::from_rgba8(width, height, slice.to_vec()))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub fn from_rgba32f(width: usize, height: usize, depth: usize, data: Vec<f32>) -> Result<VolumeTexture, &'static str> {
+        if data.len() != width * height * depth * 4 {
+            return Err("Texture data doesn't match its size!");
+        }
+
+        let slice_size = (width * height * 4).max(1);
+        VolumeTexture::from_slices(data.chunks(slice_size)
+            .map(|slice| Texture::from_rgba32f(width, height, slice.to_vec()))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    // Slices side by side from left to right, the usual layout of color grading LUTs
+    // (e.g. 256x16 for 16 slices of 16x16)
+    pub fn from_strip(strip: &Texture, depth: usize) -> Result<VolumeTexture, &'static str> {
+        if depth == 0 || strip.width / depth * depth != strip.width {
+            return Err("Strip doesn't divide into the slices evenly!");
+        }
+
+        let width = strip.width / depth;
+        VolumeTexture::from_slices((0..depth)
+            .map(|z| strip.region(z * width, 0, width, strip.height))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    // Color grading LUT that leaves colors as they are
+    pub fn identity_lut(size: usize) -> Result<VolumeTexture, &'static str> {
+        let max = size.saturating_sub(1).max(1) as f32;
+        let data = (0..size * size * size)
+            .flat_map(|i| [(i % size) as f32 / max, (i / size % size) as f32 / max, (i / (size * size)) as f32 / max, 1.0])
+            .collect();
+
+        VolumeTexture::from_rgba32f(size, size, size, data)
+    }
+
+    // Halves every axis down to 1x1x1, every texel averaging the 2x2x2 texels above it
+    pub fn generate_mipmaps(&mut self) {
+        self.mips.clear();
+
+        let mut level = self.downsample();
+        while let Some(next) = level {
+            level = next.downsample();
+            self.mips.push(next);
+        }
+    }
+
+    fn downsample(&self) -> Option<VolumeTexture> {
+        if self.width == 0 || self.height == 0 || (self.width == 1 && self.height == 1 && self.depth == 1) {
+            return None;
+        }
+
+        let depth = (self.depth / 2).max(1);
+        let slices = (0..depth).map(|z| {
+            let (z0, z1) = (z * 2, (z * 2 + 1).min(self.depth - 1));
+            let (first, second) = (&self.slices[z0], &self.slices[z1]);
+            let first = first.downsample().unwrap_or_else(|| first.average(first));
+            let second = second.downsample().unwrap_or_else(|| second.average(second));
+
+            first.average(&second)
+        }).collect();
+
+        VolumeTexture::from_slices(slices).ok()
+    }
+
+    pub fn levels(&self) -> usize {
+        1 + self.mips.len()
+    }
+
+    pub fn level(&self, level: usize) -> &VolumeTexture {
+        match level {
+            0 => self,
+            _ => self.mips.get(level - 1).or(self.mips.last()).unwrap_or(self),
+        }
+    }
+
+    pub fn slice(&self, z: usize) -> &Texture {
+        &self.slices[z]
+    }
+
+    pub fn texel(&self, x: usize, y: usize, z: usize) -> Vec4 {
+        self.slices[z].texel(x, y)
+    }
+
+    // `ddx` and `ddy` are the screen space derivatives of `uvw`, there's no anisotropic filtering
+    pub fn sample_grad(&self, sampler: &Sampler, uvw: Vec3, ddx: Vec3, ddy: Vec3) -> Vec4 {
+        let size = Vec3::new(self.width as real, self.height as real, self.depth as real);
+        let footprint = (ddx * size).length().max((ddy * size).length());
+
+        self.sample_lod(sampler, uvw, footprint.log2() + sampler.lod_bias)
+    }
+
+    pub fn sample_lod(&self, sampler: &Sampler, uvw: Vec3, lod: real) -> Vec4 {
+        sampler.mip_filter.sample(lod, self.levels(), |level| self.level(level).sample(sampler, uvw))
+    }
+
+    // Full resolution only
+    pub fn sample(&self, sampler: &Sampler, uvw: Vec3) -> Vec4 {
+        if self.width == 0 || self.height == 0 {
+            return sampler.border;
+        }
+
+        if !uvw.is_finite() {
+            return sampler.non_finite(&[sampler.wrap_u, sampler.wrap_v, sampler.wrap_w]);
+        }
+
+        let uv = uvw.truncate();
+        let z = uvw.z * self.depth as real;
+
+        match sampler.filter {
+            Filter::Nearest => self.sample_slice(sampler, uv, z.floor() as i64),
+            Filter::Bilinear => {
+                // Slice centers sit at half coordinates too
+                let z = z - 0.5;
+                let front = z.floor();
+                let back = (front as i64).saturating_add(1);
+
+                self.sample_slice(sampler, uv, front as i64).lerp(self.sample_slice(sampler, uv, back), z - front)
+            }
+        }
+    }
+
+    fn sample_slice(&self, sampler: &Sampler, uv: Vec2, z: i64) -> Vec4 {
+        match sampler.wrap_w.apply(z, self.depth) {
+            Some(z) => sampler.sample(&self.slices[z], uv),
+            None => sampler.border,
+        }
+    }
+
+    // Texel at integer coordinates, after wrapping
+    pub fn fetch(&self, sampler: &Sampler, x: i64, y: i64, z: i64) -> Vec4 {
+        match sampler.wrap_w.apply(z, self.depth) {
+            Some(z) => sampler.fetch(&self.slices[z], x, y),
+            None => sampler.border,
+        }
+    }
+}
+
+// Looks a color up in a LUT made with `identity_lut` and edited, or loaded with `from_strip`.
+// Colors outside [0, 1] are clamped.
+pub fn color_grade(lut: &VolumeTexture, color: Vec3) -> Vec3 {
+    let sampler = Sampler {
+        mip_filter: MipFilter::None,
+        ..Sampler::new(Filter::Bilinear, Wrap::Clamp)
+    };
+
+    // Black and white land on the centers of the first and last texels
+    let size = Vec3::new(lut.width as real, lut.height as real, lut.depth as real);
+    let uvw = (color.clamp(Vec3::ZERO, Vec3::ONE) * (size - Vec3::ONE) + Vec3::splat(0.5)) / size;
+
+    lut.sample(&sampler, uvw).truncate()
+}
@@ -0,0 +1,95 @@
+use rust_software_rendering::buffer::*;
+use rust_software_rendering::shader::*;
+use rust_software_rendering::state::*;
+use rust_software_rendering::utils::*;
+
+// Buffer data of the scene below, little endian f32. Rewritten by running the test with
+// UPDATE_GOLDEN set, after checking the change in output is intended.
+#[cfg(not(feature = "f32"))]
+const GOLDEN: &str = "tests/golden/deterministic_f64.bin";
+#[cfg(feature = "f32")]
+const GOLDEN: &str = "tests/golden/deterministic_f32.bin";
+
+const SIZE: usize = 32;
+
+struct Colored {
+    matrix: Mat4,
+}
+
+impl Shader for Colored {
+    type Vertex = Vec3;
+    type VertexShaderOut = Vec3;
+
+    fn vertex(&self, vertex: &Vec3) -> (Vec4, Vec3) {
+        (self.matrix * vertex.extend(1.0), *vertex + Vec3::splat(0.5))
+    }
+
+    fn fragment(&self, color: &Vec3) -> Vec4 {
+        color.extend(1.0)
+    }
+}
+
+// Rotations by angles with exact sines and cosines, so no platform's sin or cos is involved
+fn matrix() -> Mat4 {
+    let rotation_y = Mat4::from_cols(
+        Vec4::new(0.8, 0.0, -0.6, 0.0),
+        Vec4::new(0.0, 1.0, 0.0, 0.0),
+        Vec4::new(0.6, 0.0, 0.8, 0.0),
+        Vec4::W,
+    );
+    let rotation_x = Mat4::from_cols(
+        Vec4::X,
+        Vec4::new(0.0, 0.6, 0.8, 0.0),
+        Vec4::new(0.0, -0.8, 0.6, 0.0),
+        Vec4::W,
+    );
+
+    Mat4::from_scale(Vec3::splat(1.1)) * rotation_y * rotation_x
+}
+
+fn render(state: DrawState) -> Buffer {
+    let cube = (0..8)
+        .map(|i| Vec3::new((i & 1) as real, (i >> 1 & 1) as real, (i >> 2) as real) - Vec3::splat(0.5))
+        .collect::<Vec<_>>();
+    let cube_indices = [
+        0, 1, 2, 3, 1, 2, 4, 5, 6, 7, 5, 6, 0, 1, 4, 5, 1, 4,
+        2, 3, 6, 7, 3, 6, 0, 2, 4, 6, 2, 4, 1, 3, 5, 7, 3, 5,
+    ];
+    // Behind the cube and reaching past the buffer's edges, drawn last so the depth pyramid
+    // rejects parts of it
+    let backdrop = [Vec3::new(-1.3, -0.9, -0.7), Vec3::new(1.2, -1.1, -0.7), Vec3::new(0.1, 1.4, -0.6)];
+
+    let mut buffer = Buffer::new(SIZE, SIZE, 4);
+    buffer.clear();
+    let mut state = DrawState { deterministic: true, ..state };
+
+    Colored { matrix: matrix() }.draw_with(&mut buffer, &cube, &cube_indices, &mut state).unwrap();
+    Colored { matrix: Mat4::IDENTITY }.draw_with(&mut buffer, &backdrop, &[0, 1, 2], &mut state).unwrap();
+
+    buffer
+}
+
+fn bits(buffer: &Buffer) -> Vec<u32> {
+    buffer.data().iter().map(|value| value.to_bits()).collect()
+}
+
+#[test]
+fn deterministic_output() {
+    let scalar = render(DrawState::default());
+    let wide = render(DrawState { wide: true, ..Default::default() });
+    let hiz = render(DrawState { hiz: true, ..Default::default() });
+    let wide_hiz = render(DrawState { wide: true, hiz: true, ..Default::default() });
+
+    assert_eq!(bits(&scalar), bits(&wide));
+    assert_eq!(bits(&scalar), bits(&hiz));
+    assert_eq!(bits(&scalar), bits(&wide_hiz));
+
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(GOLDEN);
+    let bytes = scalar.data().iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<_>>();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, &bytes).unwrap();
+    }
+
+    let golden = std::fs::read(&path).expect("Golden image is missing, run with UPDATE_GOLDEN set to create it");
+    assert!(golden == bytes, "Output doesn't match {}", GOLDEN);
+}